@@ -0,0 +1,77 @@
+use std::future::poll_fn;
+use std::task::Poll;
+use std::time::Duration;
+
+use dapr_client::{async_trait, DaprError, DaprRequest, DaprResponse, Transport};
+use log::*;
+use spin_sdk::wit::wasi::clocks0_2_0::monotonic_clock;
+
+pub type DaprClient = dapr_client::DaprClient<SpinTransport>;
+
+// Carries Dapr requests over Spin's outbound HTTP
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinTransport;
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl Transport for SpinTransport {
+    async fn send(&self, req: DaprRequest) -> Result<DaprResponse, DaprError> {
+        info!("dapr: {:?} {:?}", req.method, req.url);
+        send_outbound(req).await
+    }
+
+    // the SDK has no timer, so wait on a WASI clock pollable through Spin's executor
+    async fn sleep(&self, duration: Duration) {
+        let deadline =
+            monotonic_clock::now().saturating_add(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX));
+        poll_fn(|cx| {
+            if monotonic_clock::now() >= deadline {
+                return Poll::Ready(());
+            }
+            spin_executor::push_waker(monotonic_clock::subscribe_instant(deadline), cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn send_outbound(req: DaprRequest) -> Result<DaprResponse, DaprError> {
+    use dapr_client::Method;
+    use spin_sdk::http::{RequestBuilder, Response};
+
+    let method = match req.method {
+        Method::Get => spin_sdk::http::Method::Get,
+        Method::Post => spin_sdk::http::Method::Post,
+        Method::Put => spin_sdk::http::Method::Put,
+        Method::Delete => spin_sdk::http::Method::Delete,
+    };
+    let mut builder = RequestBuilder::new(method, req.url);
+    for (name, value) in &req.headers {
+        builder.header(name.as_str(), value.as_str());
+    }
+    let body = req.body.unwrap_or_default();
+
+    let res: Response = spin_sdk::http::send(builder.body(body).build())
+        .await
+        .map_err(|err| DaprError::Transport(err.to_string()))?;
+    info!("dapr result: {} {:?}", res.status(), res.body());
+
+    Ok(DaprResponse {
+        status: *res.status(),
+        body: res.body().to_vec(),
+        etag: res
+            .header("etag")
+            .and_then(|etag| etag.as_str())
+            .map(str::to_string),
+    })
+}
+
+// outbound HTTP only exists inside a Spin host, native builds such as the tests can't reach Dapr with it
+#[cfg(not(target_arch = "wasm32"))]
+async fn send_outbound(req: DaprRequest) -> Result<DaprResponse, DaprError> {
+    Err(DaprError::Transport(format!(
+        "Spin outbound HTTP is unavailable outside a Spin host, can't send to {}",
+        req.url
+    )))
+}
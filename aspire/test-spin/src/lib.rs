@@ -1,16 +1,37 @@
 use anyhow::anyhow;
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
-use spin_sdk::http::{
-    IntoResponse, Method, Params, Request, RequestBuilder, Response, Router,
-};
+use sha2::{Digest, Sha256};
+use spin_sdk::http::{Params, Request, Response, Router};
 use spin_sdk::{http_component, variables};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Once;
+use std::time::Duration;
 use uuid::Uuid;
 use log::*;
 use simple_logger::SimpleLogger;
 
-const PUB_SUB_NAME: &str = "pubsub";
+pub mod dapr;
+
+use dapr::{DaprClient, SpinTransport};
+use dapr_client::{DaprError, PublishPolicy, StateItem, Transport};
+
+const DEFAULT_PUB_SUB_NAME: &str = "pubsub";
+const DEFAULT_STATE_STORE_NAME: &str = "statestore";
+const DEFAULT_PUBLISH_TIMEOUT_MS: u64 = 2000;
+const DEFAULT_PUBLISH_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_PUBLISH_RETRY_DELAY_MS: u64 = 100;
+// Dapr state stores can't list keys, so the stored item type keys are kept under this key
+const ITEM_TYPE_NAMES_KEY: &str = "item-type-names";
+// read-modify-write rounds the key list gets before giving up on concurrent writers
+const ITEM_TYPE_NAMES_ATTEMPTS: u32 = 5;
+
+// (topic, route) pairs this component subscribes to
+const SUBSCRIPTIONS: &[(&str, &str)] = &[("pinged", "/pinged"), ("ponged", "/ponged-ack")];
+
+static INIT_LOGGER: Once = Once::new();
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -18,13 +39,19 @@ struct Pinged {
     pub id: Uuid,
 }
 
-#[derive(Debug, Serialize)]
+// Dapr delivers subscribed messages wrapped in a CloudEvents envelope, payload under `data`
+#[derive(Debug, Deserialize)]
+struct CloudEvent<T> {
+    pub data: T,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Ponged {
     pub id: Uuid,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct ItemType {
     name: String,
@@ -37,57 +64,358 @@ impl TryFrom<&[u8]> for Pinged {
     type Error = anyhow::Error;
 
     fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
-        serde_json::from_slice::<Pinged>(value)
-            .with_context(|| "Could not deserialize value into Pinged model")
+        from_event_body(value, "Pinged")
+    }
+}
+
+impl TryFrom<&[u8]> for Ponged {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> std::result::Result<Self, Self::Error> {
+        from_event_body(value, "Ponged")
     }
 }
 
-fn init_logger() -> Result<()> {
+fn from_event_body<T: DeserializeOwned>(value: &[u8], model: &str) -> Result<T> {
+    let body = serde_json::from_slice::<serde_json::Value>(value)
+        .with_context(|| format!("Could not parse the {model} event body as JSON"))?;
+
+    // anything carrying envelope fields is a CloudEvent, and its own `id` must never be read as the payload id
+    let is_envelope = body.get("specversion").is_some() || body.get("data").is_some();
+    if is_envelope {
+        return serde_json::from_value::<CloudEvent<T>>(body)
+            .map(|event| event.data)
+            .with_context(|| format!("Could not deserialize CloudEvent data into {model} model"));
+    }
+
+    serde_json::from_value::<T>(body).with_context(|| {
+        format!("Could not deserialize value into {model} model, expected a CloudEvent with {model} data or a raw {model} object")
+    })
+}
+
+fn dapr_client() -> Result<DaprClient> {
+    let publish_policy = PublishPolicy {
+        timeout: Some(Duration::from_millis(variable_or(
+            "dapr_publish_timeout_ms",
+            DEFAULT_PUBLISH_TIMEOUT_MS,
+        ))),
+        max_attempts: variable_or("dapr_publish_max_attempts", DEFAULT_PUBLISH_MAX_ATTEMPTS),
+        retry_delay: Duration::from_millis(variable_or(
+            "dapr_publish_retry_delay_ms",
+            DEFAULT_PUBLISH_RETRY_DELAY_MS,
+        )),
+    };
+
+    Ok(DaprClient::new(SpinTransport, variables::get("dapr_url")?).with_publish_policy(publish_policy))
+}
+
+// unset or unparseable variables fall back to the default rather than failing the request
+fn variable_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    variables::get(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn pub_sub_name() -> String {
+    variables::get("pubsub_name").unwrap_or_else(|_| DEFAULT_PUB_SUB_NAME.to_string())
+}
+
+fn state_store_name() -> String {
+    variables::get("statestore_name").unwrap_or_else(|_| DEFAULT_STATE_STORE_NAME.to_string())
+}
+
+fn log_level() -> Result<LevelFilter> {
     const LOG_LEVEL_CONFIG_VARIABLE: &str = "loglevel";
 
-    let level: LevelFilter = variables::get(LOG_LEVEL_CONFIG_VARIABLE)?
+    variables::get(LOG_LEVEL_CONFIG_VARIABLE)?
         .parse()
-        .map_err(|e| anyhow!("parsing log level: {e}"))?;
+        .map_err(|e| anyhow!("parsing log level: {e}"))
+}
 
+fn init_logger(level: impl FnOnce() -> Result<LevelFilter>) -> Result<()> {
     SimpleLogger::new()
-        .with_level(level)
+        .with_level(level()?)
         .init()?;
 
     Ok(())
 }
 
+// the global logger can only be set once per instance, later requests reuse it
+fn init_logger_once(level: impl FnOnce() -> Result<LevelFilter>) {
+    INIT_LOGGER.call_once(|| {
+        if let Err(err) = init_logger(level) {
+            eprintln!("init_logger: {:#}", err);
+        }
+    });
+}
+
 /// A simple Spin HTTP component.
 #[http_component]
-fn handle_test_spin(req: Request) -> anyhow::Result<impl IntoResponse> {
+async fn handle_test_spin(req: Request) -> Response {
+    init_logger_once(log_level);
     //info!("Handling request to {:?}", req.header("spin-full-url"));
     info!("method={}, uri={}", req.method(), req.uri());
-    init_logger()?;
+    router().handle_async(req).await
+}
+
+fn router() -> Router {
     let mut router = Router::default();
-    router.get("/", get_home_handler);
-    router.get("/v1-get-item-types", get_item_types_handler);
-    router.post_async("/pinged", post_ping_handler);
-    router.get("/dapr/subscribe", get_dapr_subscribe_handler);
-    Ok(router.handle(req))
+    router.get_async("/", with_json_errors(get_home_handler));
+    router.get_async("/health", with_json_errors(get_health_handler));
+    router.get_async("/v1-get-item-types", with_json_errors(get_item_types_handler));
+    router.post_async("/v1-item-types", with_json_errors(post_item_type_handler));
+    router.get_async("/v1-item-types/:name", with_json_errors(get_item_type_by_name_handler));
+    router.post_async("/pinged", with_json_errors(post_ping_handler));
+    router.post_async("/ponged-ack", with_json_errors(post_ponged_ack_handler));
+    router.get_async("/dapr/subscribe", with_json_errors(get_dapr_subscribe_handler));
+    router
+}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Response>>>;
+
+// every route goes through here, so a handler error becomes a JSON error response
+// instead of failing the whole component
+fn with_json_errors<F, Fut>(handler: F) -> impl Fn(Request, Params) -> HandlerFuture
+where
+    F: Fn(Request, Params) -> Fut + 'static,
+    Fut: Future<Output = Result<Response>> + 'static,
+{
+    move |req, params| {
+        let response = handler(req, params);
+        Box::pin(async move {
+            response.await.unwrap_or_else(|err| {
+                error!("handler failed: {:#}", err);
+                json_error(error_status(&err), format!("{:#}", err))
+            })
+        })
+    }
+}
+
+// failures talking to the Dapr sidecar are upstream problems, anything else is ours
+fn error_status(err: &anyhow::Error) -> u16 {
+    match err.downcast_ref::<DaprError>() {
+        Some(DaprError::Timeout { .. }) => 504,
+        Some(_) => 502,
+        None => 500,
+    }
+}
+
+fn json_error(status: u16, msg: impl Into<String>) -> Response {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(json!({ "error": msg.into() }).to_string())
+        .build()
 }
 
-fn get_home_handler(_: Request, _: Params) -> Result<impl IntoResponse> {
+async fn get_home_handler(req: Request, _: Params) -> Result<Response> {
+    if !accepts_json(&req) {
+        return Ok(Response::builder()
+            .status(200)
+            .header("content-type", "text/plain")
+            .body("Hello, Fermyon")
+            .build());
+    }
+
     Ok(Response::builder()
         .status(200)
-        .header("content-type", "text/plain")
-        .body("Hello, Fermyon")
+        .header("content-type", "application/json")
+        .body(json!({ "message": "Hello, Fermyon" }).to_string())
         .build())
 }
 
-fn get_item_types_handler(_: Request, _: Params) -> Result<impl IntoResponse> {
-    let items = json!(get_item_types());
-    let result = bytes::Bytes::from(items.to_string());
+// only an explicit application/json in Accept opts in, wildcards keep the plain text default
+fn accepts_json(req: &Request) -> bool {
+    req.header("accept")
+        .and_then(|value| value.as_str())
+        .map(|accept| {
+            accept.split(',').any(|media_range| {
+                media_range
+                    .split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .eq_ignore_ascii_case("application/json")
+            })
+        })
+        .unwrap_or(false)
+}
+
+async fn get_health_handler(_: Request, _: Params) -> Result<Response> {
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(json!({ "status": "ok" }).to_string())
+        .build())
+}
+
+async fn get_item_types_handler(req: Request, _: Params) -> Result<Response> {
+    let item_types = load_item_types(&dapr_client()?, &state_store_name()).await?;
+
+    let items = json!(item_types).to_string();
+    let etag = etag_for(&items);
+    if if_none_match(&req, &etag) {
+        return Ok(Response::builder()
+            .status(304)
+            .header("etag", etag)
+            .body(None::<bytes::Bytes>)
+            .build());
+    }
+
+    let result = bytes::Bytes::from(items);
     Ok(Response::builder()
         .status(200)
         .header("content-type", "application/json")
+        .header("etag", etag)
         .body(Some(result))
         .build())
 }
 
+// strong validator over the serialized body, so it changes whenever the list does
+fn etag_for(body: &str) -> String {
+    format!("\"{:x}\"", Sha256::digest(body))
+}
+
+fn if_none_match(req: &Request, etag: &str) -> bool {
+    req.header("if-none-match")
+        .and_then(|value| value.as_str())
+        .map(|candidates| {
+            candidates.split(',').any(|candidate| {
+                let candidate = candidate.trim();
+                candidate == "*" || candidate.trim_start_matches("W/") == etag
+            })
+        })
+        .unwrap_or(false)
+}
+
+// the stored item types in one bulk read, or the defaults while none are stored
+async fn load_item_types<T: Transport>(
+    dapr: &dapr_client::DaprClient<T>,
+    store: &str,
+) -> Result<Vec<ItemType>> {
+    let keys = dapr
+        .get_state::<Vec<String>>(store, ITEM_TYPE_NAMES_KEY)
+        .await?
+        .unwrap_or_default();
+    if keys.is_empty() {
+        return Ok(get_item_types());
+    }
+
+    let item_types = dapr
+        .get_bulk_state::<ItemType>(store, &keys)
+        .await?
+        .into_iter()
+        .filter_map(|item| item.data)
+        .collect::<Vec<_>>();
+    if item_types.is_empty() {
+        return Ok(get_item_types());
+    }
+
+    Ok(item_types)
+}
+
+async fn get_item_type_by_name_handler(_: Request, params: Params) -> Result<Response> {
+    let name = params.get("name").unwrap_or_default();
+
+    get_item_type_by_name(&dapr_client()?, &state_store_name(), name).await
+}
+
+// looked up under the key POST saved it at, then among the defaults
+async fn get_item_type_by_name<T: Transport>(
+    dapr: &dapr_client::DaprClient<T>,
+    store: &str,
+    name: &str,
+) -> Result<Response> {
+    let stored = dapr
+        .get_state::<ItemType>(store, &item_type_key(name))
+        .await?;
+    let Some(item_type) = stored.or_else(|| {
+        get_item_types()
+            .into_iter()
+            .find(|item_type| item_type.name.eq_ignore_ascii_case(name.trim()))
+    }) else {
+        return Ok(json_error(404, format!("item type {} not found", name)));
+    };
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(json!(item_type).to_string())
+        .build())
+}
+
+async fn post_item_type_handler(req: Request, _: Params) -> Result<Response> {
+    let mut item_type = match serde_json::from_slice::<ItemType>(req.body()) {
+        Ok(item_type) => item_type,
+        Err(err) => {
+            return Ok(json_error(
+                400,
+                format!("Could not deserialize value into ItemType model: {}", err),
+            ));
+        }
+    };
+
+    item_type.name = item_type.name.trim().to_string();
+    if item_type.name.is_empty() {
+        return Ok(json_error(400, "name must not be empty"));
+    }
+    if item_type.price <= 0.0 {
+        return Ok(json_error(400, "price must be greater than 0"));
+    }
+
+    let dapr = dapr_client()?;
+    let store = state_store_name();
+    let key = item_type_key(&item_type.name);
+
+    // listed first: GET skips a listed key whose item type never got saved, but can't find an unlisted one
+    let mut attempt = 1;
+    loop {
+        match list_item_type_key(&dapr, &store, &key).await {
+            Err(err) if err.is_etag_mismatch() && attempt < ITEM_TYPE_NAMES_ATTEMPTS => attempt += 1,
+            Err(err) if err.is_etag_mismatch() => {
+                return Ok(json_error(409, "item types are being changed concurrently, retry"));
+            }
+            result => break result?,
+        }
+    }
+
+    // first-write without an ETag only saves a key nobody holds yet, however many requests race for it
+    let saved = dapr
+        .save_state(&store, &[StateItem::new(key.as_str(), &item_type)?.first_write(None)])
+        .await;
+    match saved {
+        Err(err) if err.is_etag_mismatch() => {
+            return Ok(json_error(409, format!("item type {} already exists", item_type.name)));
+        }
+        saved => saved?,
+    }
+
+    Ok(Response::builder()
+        .status(201)
+        .header("content-type", "application/json")
+        .body(json!(item_type).to_string())
+        .build())
+}
+
+// names differing only in case or surrounding space are the same item type
+fn item_type_key(name: &str) -> String {
+    name.trim().to_ascii_uppercase()
+}
+
+// adds `key` to the stored key list unless it is there already, saved at the ETag it was read at
+async fn list_item_type_key(dapr: &DaprClient, store: &str, key: &str) -> Result<(), DaprError> {
+    let entry = dapr.get_state_entry::<Vec<String>>(store, ITEM_TYPE_NAMES_KEY).await?;
+    let mut keys = entry.value.unwrap_or_default();
+    if keys.iter().any(|listed| listed == key) {
+        return Ok(());
+    }
+    keys.push(key.to_string());
+
+    dapr.save_state(store, &[StateItem::new(ITEM_TYPE_NAMES_KEY, &keys)?.first_write(entry.etag)])
+        .await
+}
+
 fn get_item_types() -> Vec<ItemType> {
     vec![
         ItemType {
@@ -105,22 +433,8 @@ fn get_item_types() -> Vec<ItemType> {
     ]
 }
 
-fn get_dapr_subscribe_handler(_: Request, _params: Params) -> Result<impl IntoResponse> {
-    let model = json!([
-        {
-            "pubsubname": PUB_SUB_NAME,
-            "topic": "pinged",
-            "routes": {
-              "rules": [
-                {
-                  "match": "event.type == 'pinged'",
-                  "path": "/pinged"
-                },
-              ],
-              "default": "/pinged"
-            }
-        }
-    ]);
+async fn get_dapr_subscribe_handler(_: Request, _params: Params) -> Result<Response> {
+    let model = subscriptions(&pub_sub_name());
 
     let result = bytes::Bytes::from(model.to_string());
 
@@ -131,46 +445,333 @@ fn get_dapr_subscribe_handler(_: Request, _params: Params) -> Result<impl IntoRe
         .build())
 }
 
-async fn post_ping_handler(req: Request, _params: Params) -> Result<impl IntoResponse> {
-    let dapr_url = variables::get("dapr_url")?;
-    info!("# dapr_url: {}", dapr_url);
+fn subscriptions(pubsub_name: &str) -> serde_json::Value {
+    let model = SUBSCRIPTIONS
+        .iter()
+        .map(|(topic, route)| {
+            json!({
+                "pubsubname": pubsub_name,
+                "topic": topic,
+                "routes": {
+                  "rules": [
+                    {
+                      "match": format!("event.type == '{}'", topic),
+                      "path": route
+                    },
+                  ],
+                  "default": route
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    json!(model)
+}
 
-    let Ok(model) = Pinged::try_from(req.body()) else {
-        return Ok(Response::builder()
-            .status(400)
-            .body(Some("Something wrong."))
-            .build());
+async fn post_ping_handler(req: Request, _params: Params) -> Result<Response> {
+    let dapr = dapr_client()?;
+    info!("# dapr_url: {}", dapr.base_url());
+
+    post_ping(&dapr, &pub_sub_name(), req.body()).await
+}
+
+// answers a pinged event with a ponged one on `pubsub_name`, whatever transport carries it
+async fn post_ping<T: Transport>(
+    dapr: &dapr_client::DaprClient<T>,
+    pubsub_name: &str,
+    body: &[u8],
+) -> Result<Response> {
+    let model = match Pinged::try_from(body) {
+        Ok(model) => model,
+        Err(err) => {
+            return Ok(json_error(400, format!("{:#}", err)));
+        }
     };
 
     info!("post_ping_handler: {:?}", json!(model).to_string());
 
-    pub_ponged(
-        dapr_url.as_str(),
-        PUB_SUB_NAME,
-        "ponged",
-        Ponged { id: model.id },
-    ).await;
+    let ponged = Ponged { id: model.id };
+    // a non-2xx status makes Dapr redeliver the pinged event, so the publish gets another go
+    if let Err(err) = dapr.publish(pubsub_name, "ponged", &ponged).await {
+        error!("publish ponged: {:#}", err);
+        let status = if matches!(err, DaprError::Timeout { .. }) { 504 } else { 502 };
+        return Ok(json_error(status, "Could not publish ponged event."));
+    }
+
+    Ok(Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .body(Some(json!(ponged).to_string()))
+        .build())
+}
+
+async fn post_ponged_ack_handler(req: Request, _params: Params) -> Result<Response> {
+    let model = match Ponged::try_from(req.body()) {
+        Ok(model) => model,
+        Err(err) => {
+            // a malformed event will never parse, tell Dapr to drop it rather than retry
+            warn!("post_ponged_ack_handler: {:#}", err);
+            return Ok(Response::builder()
+                .status(200)
+                .header("content-type", "application/json")
+                .body(json!({ "status": "DROP" }).to_string())
+                .build());
+        }
+    };
+
+    info!("post_ponged_ack_handler: {:?}", json!(model).to_string());
 
     Ok(Response::builder()
         .status(200)
         .header("content-type", "application/json")
-        .body(Some(""))
+        .body(json!({ "status": "SUCCESS" }).to_string())
         .build())
 }
 
-async fn pub_ponged(dapr_url: &str, pubsub_name: &str, topic: &str, e: Ponged) {
-    let url = format!("{}/v1.0/publish/{}/{}", dapr_url, pubsub_name, topic);
-    info!("pub_ponged: {:?}", url.to_string());
-    info!("pub_ponged: {:?}", json!(e).to_string());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dapr_client::{async_trait, DaprRequest, DaprResponse};
+    use futures::executor::block_on;
+    use spin_sdk::http::Method;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    type Sent = Arc<Mutex<Vec<DaprRequest>>>;
+
+    // answers every Dapr call with `status` and the next of `bodies`, or an empty body once they
+    // run out, keeping the requests it was sent
+    struct StubTransport {
+        status: u16,
+        bodies: Mutex<VecDeque<serde_json::Value>>,
+        sent: Sent,
+    }
+
+    impl StubTransport {
+        fn client(status: u16) -> (dapr_client::DaprClient<StubTransport>, Sent) {
+            StubTransport::answering(status, vec![])
+        }
+
+        fn reading(
+            bodies: Vec<serde_json::Value>,
+        ) -> (dapr_client::DaprClient<StubTransport>, Sent) {
+            StubTransport::answering(200, bodies)
+        }
+
+        fn answering(
+            status: u16,
+            bodies: Vec<serde_json::Value>,
+        ) -> (dapr_client::DaprClient<StubTransport>, Sent) {
+            let sent = Sent::default();
+            let transport = StubTransport {
+                status,
+                bodies: Mutex::new(bodies.into()),
+                sent: sent.clone(),
+            };
+            let dapr = dapr_client::DaprClient::new(transport, "http://localhost:3500")
+                .with_publish_policy(PublishPolicy {
+                    timeout: None,
+                    max_attempts: 2,
+                    retry_delay: Duration::ZERO,
+                });
+            (dapr, sent)
+        }
+    }
+
+    #[async_trait]
+    impl Transport for StubTransport {
+        async fn send(&self, req: DaprRequest) -> std::result::Result<DaprResponse, DaprError> {
+            self.sent.lock().unwrap().push(req);
+            let body = self.bodies.lock().unwrap().pop_front();
+            Ok(DaprResponse {
+                status: self.status,
+                body: body
+                    .map(|body| body.to_string().into_bytes())
+                    .unwrap_or_default(),
+                etag: None,
+            })
+        }
+
+        async fn sleep(&self, _: Duration) {}
+    }
+
+    // what Dapr reads from /dapr/subscribe, so a misspelt or extra field fails the test
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Subscription {
+        pubsubname: String,
+        topic: String,
+        routes: Routes,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Routes {
+        rules: Vec<Rule>,
+        default: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Rule {
+        r#match: String,
+        path: String,
+    }
+
+    fn typed_subscriptions(pubsub_name: &str) -> Vec<Subscription> {
+        serde_json::from_value(subscriptions(pubsub_name)).unwrap()
+    }
+
+    fn status(method: Method, path: &str) -> u16 {
+        let req = Request::new(method, format!("http://localhost{}", path));
+        *block_on(router().handle_async(req)).status()
+    }
+
+    #[test]
+    fn subscribes_pinged_and_ponged_on_the_pubsub() {
+        let subscriptions = typed_subscriptions("pubsub");
+
+        let topics = subscriptions
+            .iter()
+            .map(|subscription| subscription.topic.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(topics, vec!["pinged", "ponged"]);
+        assert!(subscriptions
+            .iter()
+            .all(|subscription| subscription.pubsubname == "pubsub"));
+    }
+
+    #[test]
+    fn uses_the_configured_pubsub_name() {
+        assert!(typed_subscriptions("orders")
+            .iter()
+            .all(|subscription| subscription.pubsubname == "orders"));
+    }
+
+    #[test]
+    fn routes_every_subscription_to_a_post_handler() {
+        for Subscription { topic, routes, .. } in typed_subscriptions("pubsub") {
+            let route = routes.default.as_str();
+
+            assert_eq!(routes.rules.len(), 1, "{}", topic);
+            assert_eq!(routes.rules[0].path, route, "{}", topic);
+            assert_eq!(routes.rules[0].r#match, format!("event.type == '{}'", topic));
+            // registered for POST only, so any other method is refused rather than not found
+            assert_eq!(status(Method::Get, route), 405, "{} -> {}", topic, route);
+        }
+    }
+
+    #[test]
+    fn initializes_the_logger_once() {
+        init_logger_once(|| Ok(LevelFilter::Info));
+        // a second request must neither set the global logger again nor read the level
+        init_logger_once(|| panic!("the logger was initialized twice"));
+
+        assert_eq!(log::max_level(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn serves_the_subscriptions_at_the_root() {
+        assert_eq!(status(Method::Post, "/dapr/subscribe"), 405);
+        assert_eq!(status(Method::Get, "/no-such-route"), 404);
+    }
+
+    #[test]
+    fn answers_502_when_the_ponged_publish_fails() {
+        let (dapr, sent) = StubTransport::client(500);
+        let body = json!({ "id": Uuid::new_v4() }).to_string();
+
+        let response = block_on(post_ping(&dapr, "pubsub", body.as_bytes())).unwrap();
+
+        assert_eq!(*response.status(), 502);
+        // a 5xx from the sidecar is retried before giving up
+        assert_eq!(sent.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn echoes_the_pinged_id_as_ponged() {
+        let (dapr, sent) = StubTransport::client(204);
+        let id = Uuid::new_v4();
+        let body = json!({ "specversion": "1.0", "id": "envelope-id", "data": { "id": id } });
+
+        let response = block_on(post_ping(&dapr, "pubsub", body.to_string().as_bytes())).unwrap();
+
+        assert_eq!(*response.status(), 200);
+        let ponged = serde_json::from_slice::<serde_json::Value>(response.body()).unwrap();
+        assert_eq!(ponged, json!({ "id": id }));
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].url,
+            "http://localhost:3500/v1.0/publish/pubsub/ponged"
+        );
+        let published =
+            serde_json::from_slice::<serde_json::Value>(sent[0].body.as_deref().unwrap()).unwrap();
+        assert_eq!(published, json!({ "id": id }));
+    }
+
+    fn mocha() -> serde_json::Value {
+        json!({ "name": "MOCHA", "itemType": 5, "price": 4.0, "image": "img/MOCHA.png" })
+    }
+
+    fn response_json(response: &Response) -> serde_json::Value {
+        serde_json::from_slice(response.body()).unwrap()
+    }
+
+    #[test]
+    fn reads_an_item_type_by_name_from_the_state_store() {
+        let (dapr, sent) = StubTransport::reading(vec![mocha()]);
+
+        let response = block_on(get_item_type_by_name(&dapr, "statestore", " mocha ")).unwrap();
 
-    let body = bytes::Bytes::from(json!(e).to_string());
-    let result = spin_sdk::http::send::<_, Response>(
-        RequestBuilder::new(Method::Post, url)
-            .header("content-type", "application/json")
-            .body(Some(body))
-            .build(),
-    );
+        assert_eq!(*response.status(), 200);
+        assert_eq!(response_json(&response), mocha());
+        assert_eq!(
+            sent.lock().unwrap()[0].url,
+            "http://localhost:3500/v1.0/state/statestore/MOCHA"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_default_item_types_by_name() {
+        let (dapr, _) = StubTransport::client(204);
+
+        let response = block_on(get_item_type_by_name(&dapr, "statestore", "cappuccino")).unwrap();
+        assert_eq!(*response.status(), 200);
+        assert_eq!(response_json(&response)["name"], "CAPPUCCINO");
+
+        let response = block_on(get_item_type_by_name(&dapr, "statestore", "latte")).unwrap();
+        assert_eq!(*response.status(), 404);
+    }
+
+    #[test]
+    fn lists_the_stored_item_types_in_one_bulk_read() {
+        let (dapr, sent) = StubTransport::reading(vec![
+            json!(["MOCHA", "LATTE"]),
+            json!([{ "key": "MOCHA", "data": mocha() }, { "key": "LATTE" }]),
+        ]);
 
-    let result_unwrapped = result.await.unwrap();
-    info!("pub_ponged result: {:?}", result_unwrapped.body());
+        let item_types = block_on(load_item_types(&dapr, "statestore")).unwrap();
+
+        assert_eq!(json!(item_types), json!([mocha()]));
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            sent[1].url,
+            "http://localhost:3500/v1.0/state/statestore/bulk"
+        );
+        let keys =
+            serde_json::from_slice::<serde_json::Value>(sent[1].body.as_deref().unwrap()).unwrap();
+        assert_eq!(keys, json!({ "keys": ["MOCHA", "LATTE"] }));
+    }
+
+    #[test]
+    fn lists_the_default_item_types_while_none_are_stored() {
+        let (dapr, sent) = StubTransport::client(204);
+
+        let item_types = block_on(load_item_types(&dapr, "statestore")).unwrap();
+
+        assert_eq!(json!(item_types), json!(get_item_types()));
+        assert_eq!(sent.lock().unwrap().len(), 1);
+    }
 }
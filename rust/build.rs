@@ -0,0 +1,40 @@
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Embeds GIT_HASH and BUILD_TIMESTAMP for the counter's /version endpoint.
+// GIT_HASH can be passed in where there is no .git directory, e.g. docker builds.
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    // any source change reruns this, so BUILD_TIMESTAMP follows incremental builds too
+    println!("cargo:rerun-if-changed=src");
+
+    let git_hash = std::env::var("GIT_HASH").ok().or_else(|| {
+        let git_dir = git(&["rev-parse", "--git-dir"])?;
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/refs", git_dir);
+        git(&["rev-parse", "--short", "HEAD"])
+    });
+    println!(
+        "cargo:rustc-env=GIT_HASH={}",
+        git_hash.unwrap_or_else(|| "unknown".to_string())
+    );
+
+    let build_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", build_timestamp);
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|value| value.trim().to_string())
+}
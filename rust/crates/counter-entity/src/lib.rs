@@ -2,5 +2,6 @@
 
 pub mod prelude;
 
+pub mod idempotency_keys;
 pub mod line_items;
 pub mod orders;
@@ -14,8 +14,8 @@ pub struct Model {
     pub item_status: i32,
     pub is_barista_order: bool,
     pub order_id: Option<Uuid>,
-    pub created: DateTimeWithTimeZone,
-    pub updated: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -36,4 +36,16 @@ impl Related<super::orders::Entity> for Entity {
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        // inserts take created_at/updated_at from the column defaults
+        if !insert {
+            self.updated_at = sea_orm::Set(chrono::Utc::now().into());
+        }
+        Ok(self)
+    }
+}
@@ -10,19 +10,42 @@ pub struct Model {
     pub order_source: i32,
     pub loyalty_member_id: Uuid,
     pub order_status: i32,
-    pub updated: Option<DateTimeWithTimeZone>,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+    pub version: i32,
+    pub deleted_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::idempotency_keys::Entity")]
+    IdempotencyKeys,
     #[sea_orm(has_many = "super::line_items::Entity")]
     LineItems,
 }
 
+impl Related<super::idempotency_keys::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IdempotencyKeys.def()
+    }
+}
+
 impl Related<super::line_items::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::LineItems.def()
     }
 }
 
-impl ActiveModelBehavior for ActiveModel {}
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    async fn before_save<C>(mut self, _db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        // inserts take created_at/updated_at from the column defaults
+        if !insert {
+            self.updated_at = sea_orm::Set(chrono::Utc::now().into());
+        }
+        Ok(self)
+    }
+}
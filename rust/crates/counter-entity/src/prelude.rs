@@ -1,4 +1,5 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.3
 
+pub use super::idempotency_keys::Entity as IdempotencyKeys;
 pub use super::line_items::Entity as LineItems;
 pub use super::orders::Entity as Orders;
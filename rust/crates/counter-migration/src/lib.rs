@@ -0,0 +1,24 @@
+pub use sea_orm_migration::prelude::*;
+
+mod m20230501_000001_create_order_tables;
+mod m20230601_000001_create_idempotency_keys;
+mod m20230701_000001_add_order_version;
+mod m20230801_000001_add_timestamps;
+mod m20230901_000001_index_orders_loyalty_member;
+mod m20231001_000001_add_order_deleted_at;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20230501_000001_create_order_tables::Migration),
+            Box::new(m20230601_000001_create_idempotency_keys::Migration),
+            Box::new(m20230701_000001_add_order_version::Migration),
+            Box::new(m20230801_000001_add_timestamps::Migration),
+            Box::new(m20230901_000001_index_orders_loyalty_member::Migration),
+            Box::new(m20231001_000001_add_order_deleted_at::Migration),
+        ]
+    }
+}
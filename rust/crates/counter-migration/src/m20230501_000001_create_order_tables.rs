@@ -0,0 +1,65 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                CREATE SCHEMA IF NOT EXISTS "order";
+
+                CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
+
+                CREATE TABLE IF NOT EXISTS "order".orders (
+                    id uuid NOT NULL DEFAULT (uuid_generate_v4()),
+                    order_source integer NOT NULL,
+                    loyalty_member_id uuid NOT NULL,
+                    order_status integer NOT NULL,
+                    updated timestamp with time zone NULL,
+                    CONSTRAINT pk_orders PRIMARY KEY (id)
+                );
+
+                CREATE TABLE IF NOT EXISTS "order".line_items (
+                    id uuid NOT NULL DEFAULT (uuid_generate_v4()),
+                    item_type integer NOT NULL,
+                    name text NOT NULL,
+                    price numeric NOT NULL,
+                    item_status integer NOT NULL,
+                    is_barista_order boolean NOT NULL,
+                    order_id uuid NULL,
+                    created timestamp with time zone NOT NULL DEFAULT (now()),
+                    updated timestamp with time zone NULL,
+                    CONSTRAINT pk_line_items PRIMARY KEY (id),
+                    CONSTRAINT fk_line_items_orders_order_temp_id FOREIGN KEY (order_id) REFERENCES "order".orders (id)
+                );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS ix_line_items_id ON "order".line_items (id);
+
+                CREATE INDEX IF NOT EXISTS ix_line_items_order_id ON "order".line_items (order_id);
+
+                CREATE UNIQUE INDEX IF NOT EXISTS ix_orders_id ON "order".orders (id);
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                DROP TABLE IF EXISTS "order".line_items;
+                DROP TABLE IF EXISTS "order".orders;
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
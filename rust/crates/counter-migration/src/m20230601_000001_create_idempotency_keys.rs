@@ -0,0 +1,35 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                CREATE TABLE IF NOT EXISTS "order".idempotency_keys (
+                    key text NOT NULL,
+                    order_id uuid NOT NULL,
+                    created timestamp with time zone NOT NULL DEFAULT (now()),
+                    CONSTRAINT pk_idempotency_keys PRIMARY KEY (key),
+                    CONSTRAINT fk_idempotency_keys_orders_order_id FOREIGN KEY (order_id) REFERENCES "order".orders (id)
+                );
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(r#"DROP TABLE IF EXISTS "order".idempotency_keys;"#)
+            .await?;
+
+        Ok(())
+    }
+}
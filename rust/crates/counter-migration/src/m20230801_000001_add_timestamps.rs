@@ -0,0 +1,59 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                ALTER TABLE "order".orders
+                    ADD COLUMN IF NOT EXISTS created_at timestamp with time zone NOT NULL DEFAULT (now()),
+                    ADD COLUMN IF NOT EXISTS updated_at timestamp with time zone NOT NULL DEFAULT (now());
+
+                -- nothing ever wrote orders.updated, keep whatever it holds and drop it
+                UPDATE "order".orders SET updated_at = updated WHERE updated IS NOT NULL;
+                ALTER TABLE "order".orders DROP COLUMN updated;
+
+                -- line items already knew when they were written, their columns become the new ones
+                ALTER TABLE "order".line_items RENAME COLUMN created TO created_at;
+                UPDATE "order".line_items SET updated = created_at WHERE updated IS NULL;
+                ALTER TABLE "order".line_items RENAME COLUMN updated TO updated_at;
+                ALTER TABLE "order".line_items
+                    ALTER COLUMN updated_at SET DEFAULT (now()),
+                    ALTER COLUMN updated_at SET NOT NULL;
+
+                CREATE INDEX IF NOT EXISTS ix_orders_created_at ON "order".orders (created_at);
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                DROP INDEX IF EXISTS "order".ix_orders_created_at;
+
+                ALTER TABLE "order".line_items
+                    ALTER COLUMN updated_at DROP NOT NULL,
+                    ALTER COLUMN updated_at DROP DEFAULT;
+                ALTER TABLE "order".line_items RENAME COLUMN updated_at TO updated;
+                ALTER TABLE "order".line_items RENAME COLUMN created_at TO created;
+
+                ALTER TABLE "order".orders ADD COLUMN updated timestamp with time zone NULL;
+                UPDATE "order".orders SET updated = updated_at;
+                ALTER TABLE "order".orders DROP COLUMN created_at, DROP COLUMN updated_at;
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+}
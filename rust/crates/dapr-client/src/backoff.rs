@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+// Longest a retry waits once the doubling has grown past it, the same cap the counter's database
+// connect uses.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Wait before retrying after failed `attempt` (1-based): `base` doubled after every attempt,
+// saturating instead of overflowing and capped at `MAX_RETRY_DELAY`, or at `base` when that is
+// configured longer.
+pub fn backoff_delay(base: Duration, attempt: u32) -> Duration {
+    let factor = 2u32
+        .checked_pow(attempt.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+
+    base.saturating_mul(factor).min(MAX_RETRY_DELAY.max(base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_after_every_attempt() {
+        let base = Duration::from_millis(100);
+
+        assert_eq!(backoff_delay(base, 1), base);
+        assert_eq!(backoff_delay(base, 2), base * 2);
+        assert_eq!(backoff_delay(base, 4), base * 8);
+    }
+
+    #[test]
+    fn caps_instead_of_overflowing() {
+        let base = Duration::from_millis(100);
+
+        assert_eq!(backoff_delay(base, 20), MAX_RETRY_DELAY);
+        assert_eq!(backoff_delay(base, 33), MAX_RETRY_DELAY);
+        assert_eq!(backoff_delay(base, u32::MAX), MAX_RETRY_DELAY);
+        assert_eq!(backoff_delay(Duration::MAX, 2), Duration::MAX);
+    }
+
+    #[test]
+    fn keeps_a_base_longer_than_the_cap() {
+        let base = Duration::from_secs(60);
+
+        assert_eq!(backoff_delay(base, 1), base);
+        assert_eq!(backoff_delay(base, 5), base);
+    }
+}
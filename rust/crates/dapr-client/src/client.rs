@@ -0,0 +1,586 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    backoff::backoff_delay,
+    error::DaprError,
+    timeout::with_timeout,
+    transport::{DaprRequest, DaprResponse, Method, Transport},
+};
+
+// The sidecar on localhost at DAPR_HTTP_PORT, which Dapr sets for the app it runs, otherwise `dapr_url`
+pub fn sidecar_url(dapr_http_port: Option<u16>, dapr_url: &str) -> String {
+    match dapr_http_port {
+        Some(port) => format!("http://localhost:{}", port),
+        None => dapr_url.trim_end_matches('/').to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DaprClient<T> {
+    transport: T,
+    base_url: String,
+    publish_policy: PublishPolicy,
+}
+
+// How hard `publish` tries: each attempt is cut off after `timeout`, and retriable failures are
+// tried again up to `max_attempts` in total, waiting `retry_delay` doubled after every attempt
+// up to `MAX_RETRY_DELAY`.
+// Our events are safe to deliver twice, so a publish that timed out after all is not a problem.
+// A publish may take `max_attempts` timeouts plus the delays, so never make one inside a DB transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublishPolicy {
+    pub timeout: Option<Duration>,
+    pub max_attempts: u32,
+    pub retry_delay: Duration,
+}
+
+impl Default for PublishPolicy {
+    fn default() -> Self {
+        PublishPolicy {
+            timeout: None,
+            max_attempts: 1,
+            retry_delay: Duration::ZERO,
+        }
+    }
+}
+
+// One entry of a state save, e.g. with `ttlInSeconds` metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct StateItem {
+    pub key: String,
+    pub value: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<StateOptions>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct StateOptions {
+    pub concurrency: Concurrency,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Concurrency {
+    FirstWrite,
+    LastWrite,
+}
+
+// A stored value with the ETag it was read at, to hand back to `StateItem::first_write`
+#[derive(Debug)]
+pub struct StateEntry<V> {
+    pub value: Option<V>,
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkStateItem<V> {
+    pub key: String,
+    // absent for keys the store doesn't hold
+    pub data: Option<V>,
+}
+
+impl StateItem {
+    pub fn new<V: Serialize + ?Sized>(key: impl Into<String>, value: &V) -> Result<Self, DaprError> {
+        Ok(StateItem {
+            key: key.into(),
+            value: serde_json::to_value(value).map_err(DaprError::Encode)?,
+            etag: None,
+            options: None,
+            metadata: HashMap::new(),
+        })
+    }
+
+    // saved only while the stored value is still at `etag`, or with no etag only if the key is absent;
+    // otherwise the save fails with a 409, see `DaprError::is_etag_mismatch`
+    pub fn first_write(mut self, etag: Option<String>) -> Self {
+        self.etag = etag;
+        self.options = Some(StateOptions {
+            concurrency: Concurrency::FirstWrite,
+        });
+        self
+    }
+
+    pub fn with_metadata(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(name.into(), value.into());
+        self
+    }
+}
+
+impl<T: Transport> DaprClient<T> {
+    pub fn new(transport: T, base_url: impl Into<String>) -> Self {
+        DaprClient {
+            transport,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            publish_policy: PublishPolicy::default(),
+        }
+    }
+
+    pub fn with_publish_policy(mut self, publish_policy: PublishPolicy) -> Self {
+        self.publish_policy = PublishPolicy {
+            max_attempts: publish_policy.max_attempts.max(1),
+            ..publish_policy
+        };
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub async fn publish<P: Serialize + ?Sized>(
+        &self,
+        pubsub_name: &str,
+        topic: &str,
+        payload: &P,
+    ) -> Result<(), DaprError> {
+        let url = format!("{}/v1.0/publish/{}/{}", self.base_url, pubsub_name, topic);
+        let body = serde_json::to_vec(payload).map_err(DaprError::Encode)?;
+        let operation = format!("publish {}", topic);
+        let policy = self.publish_policy;
+
+        let mut attempt = 1;
+        loop {
+            let sent = self.send(Method::Post, url.clone(), Some(body.clone()), &operation);
+            let result = match policy.timeout {
+                Some(timeout) => with_timeout(sent, self.transport.sleep(timeout))
+                    .await
+                    .unwrap_or_else(|| {
+                        Err(DaprError::Timeout {
+                            operation: operation.clone(),
+                            timeout,
+                        })
+                    }),
+                None => sent.await,
+            };
+
+            match result {
+                Ok(_) => return Ok(()),
+                Err(err) if err.is_retriable() && attempt < policy.max_attempts => {
+                    self.transport
+                        .sleep(backoff_delay(policy.retry_delay, attempt))
+                        .await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // succeeds once the sidecar and its components are up
+    pub async fn healthz(&self) -> Result<(), DaprError> {
+        let url = format!("{}/v1.0/healthz", self.base_url);
+
+        self.send(Method::Get, url, None, "healthz").await?;
+
+        Ok(())
+    }
+
+    // calls `path` on another Dapr app through service invocation
+    pub async fn invoke<B, R>(
+        &self,
+        app_id: &str,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<R, DaprError>
+    where
+        B: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        let url = format!(
+            "{}/v1.0/invoke/{}/method/{}",
+            self.base_url,
+            app_id,
+            path.trim_start_matches('/')
+        );
+        let body = body
+            .map(|body| serde_json::to_vec(body).map_err(DaprError::Encode))
+            .transpose()?;
+
+        let operation = format!("invoke {}/{}", app_id, path.trim_start_matches('/'));
+        let res = self.send(method, url, body, &operation).await?;
+        decode(&res, operation)
+    }
+
+    pub async fn get_state<V: DeserializeOwned>(
+        &self,
+        store_name: &str,
+        key: &str,
+    ) -> Result<Option<V>, DaprError> {
+        Ok(self.get_state_entry(store_name, key).await?.value)
+    }
+
+    // like `get_state`, keeping the ETag for a read-modify-write
+    pub async fn get_state_entry<V: DeserializeOwned>(
+        &self,
+        store_name: &str,
+        key: &str,
+    ) -> Result<StateEntry<V>, DaprError> {
+        let url = format!("{}/v1.0/state/{}/{}", self.base_url, store_name, key);
+
+        let operation = format!("get state {}", key);
+        let res = self.send(Method::Get, url, None, &operation).await?;
+        // Dapr answers 204 for keys it doesn't hold
+        if res.status == 204 || res.body.is_empty() {
+            return Ok(StateEntry {
+                value: None,
+                etag: None,
+            });
+        }
+
+        Ok(StateEntry {
+            value: Some(decode(&res, operation)?),
+            etag: res.etag,
+        })
+    }
+
+    pub async fn get_bulk_state<V: DeserializeOwned>(
+        &self,
+        store_name: &str,
+        keys: &[String],
+    ) -> Result<Vec<BulkStateItem<V>>, DaprError> {
+        let url = format!("{}/v1.0/state/{}/bulk", self.base_url, store_name);
+        let body = serde_json::to_vec(&serde_json::json!({ "keys": keys }))
+            .map_err(DaprError::Encode)?;
+
+        let operation = "get bulk state".to_string();
+        let res = self.send(Method::Post, url, Some(body), &operation).await?;
+        decode(&res, operation)
+    }
+
+    // every item goes in a single request, so Dapr saves them together
+    pub async fn save_state(&self, store_name: &str, items: &[StateItem]) -> Result<(), DaprError> {
+        let url = format!("{}/v1.0/state/{}", self.base_url, store_name);
+        let body = serde_json::to_vec(items).map_err(DaprError::Encode)?;
+
+        self.send(Method::Post, url, Some(body), "save state")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<Vec<u8>>,
+        operation: &str,
+    ) -> Result<DaprResponse, DaprError> {
+        let headers = if body.is_some() {
+            vec![("content-type".to_string(), "application/json".to_string())]
+        } else {
+            vec![]
+        };
+
+        let res = self
+            .transport
+            .send(DaprRequest {
+                method,
+                url,
+                headers,
+                body,
+            })
+            .await?;
+        if !res.is_success() {
+            return Err(DaprError::Status {
+                operation: operation.to_string(),
+                status: res.status,
+            });
+        }
+
+        Ok(res)
+    }
+}
+
+fn decode<V: DeserializeOwned>(res: &DaprResponse, operation: String) -> Result<V, DaprError> {
+    serde_json::from_slice(&res.body).map_err(|source| DaprError::Decode { operation, source })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    use super::*;
+
+    // `None` is a send that never completes, so only a timeout ends it
+    type Reply = Option<Result<DaprResponse, DaprError>>;
+
+    #[derive(Default)]
+    struct Recorded {
+        replies: VecDeque<Reply>,
+        sent: Vec<DaprRequest>,
+        slept: Vec<Duration>,
+    }
+
+    // Answers with the queued replies in order and records what the client sent and slept
+    #[derive(Clone, Default)]
+    struct StubTransport(Arc<Mutex<Recorded>>);
+
+    #[async_trait::async_trait]
+    impl Transport for StubTransport {
+        async fn send(&self, req: DaprRequest) -> Result<DaprResponse, DaprError> {
+            let reply = {
+                let mut recorded = self.0.lock().unwrap();
+                recorded.sent.push(req);
+                recorded
+                    .replies
+                    .pop_front()
+                    .expect("a reply for every request")
+            };
+            match reply {
+                Some(reply) => reply,
+                None => std::future::pending().await,
+            }
+        }
+
+        async fn sleep(&self, duration: Duration) {
+            self.0.lock().unwrap().slept.push(duration);
+        }
+    }
+
+    impl StubTransport {
+        fn client(replies: impl IntoIterator<Item = Reply>) -> (DaprClient<StubTransport>, Self) {
+            let transport = StubTransport::default();
+            transport.0.lock().unwrap().replies.extend(replies);
+
+            (
+                DaprClient::new(transport.clone(), "http://localhost:3500/"),
+                transport,
+            )
+        }
+
+        fn sent(&self) -> Vec<DaprRequest> {
+            self.0.lock().unwrap().sent.clone()
+        }
+
+        fn slept(&self) -> Vec<Duration> {
+            self.0.lock().unwrap().slept.clone()
+        }
+    }
+
+    fn ok(body: serde_json::Value) -> Reply {
+        Some(Ok(DaprResponse {
+            status: 200,
+            body: serde_json::to_vec(&body).unwrap(),
+            etag: None,
+        }))
+    }
+
+    fn status(status: u16) -> Reply {
+        Some(Ok(DaprResponse {
+            status,
+            body: vec![],
+            etag: None,
+        }))
+    }
+
+    fn body_json(req: &DaprRequest) -> serde_json::Value {
+        serde_json::from_slice(req.body.as_deref().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn publishes_to_the_topic_url() {
+        let (client, transport) = StubTransport::client([status(204)]);
+
+        block_on(client.publish("orderpubsub", "orderup", &json!({ "id": 1 }))).unwrap();
+
+        let sent = transport.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].method, Method::Post);
+        assert_eq!(
+            sent[0].url,
+            "http://localhost:3500/v1.0/publish/orderpubsub/orderup"
+        );
+        assert_eq!(
+            sent[0].headers,
+            vec![("content-type".to_string(), "application/json".to_string())]
+        );
+        assert_eq!(body_json(&sent[0]), json!({ "id": 1 }));
+    }
+
+    #[test]
+    fn invokes_the_app_method_url() {
+        let (client, transport) = StubTransport::client([ok(json!({ "price": 3.5 }))]);
+
+        let res: serde_json::Value =
+            block_on(client.invoke::<(), _>("productapi", Method::Get, "/v1-get-item-types", None))
+                .unwrap();
+
+        assert_eq!(res, json!({ "price": 3.5 }));
+        let sent = transport.sent();
+        assert_eq!(sent[0].method, Method::Get);
+        assert_eq!(
+            sent[0].url,
+            "http://localhost:3500/v1.0/invoke/productapi/method/v1-get-item-types"
+        );
+        assert!(sent[0].headers.is_empty());
+        assert!(sent[0].body.is_none());
+    }
+
+    #[test]
+    fn reads_and_saves_state_under_the_store_url() {
+        let (client, transport) = StubTransport::client([
+            Some(Ok(DaprResponse {
+                status: 200,
+                body: b"\"LATTE\"".to_vec(),
+                etag: Some("3".to_string()),
+            })),
+            status(204),
+            status(204),
+        ]);
+
+        let entry = block_on(client.get_state_entry::<String>("statestore", "item-2")).unwrap();
+        assert_eq!(entry.value.as_deref(), Some("LATTE"));
+        assert_eq!(entry.etag.as_deref(), Some("3"));
+        let missing = block_on(client.get_state::<String>("statestore", "item-42")).unwrap();
+        assert_eq!(missing, None);
+        let item = StateItem::new("item-2", "MOCHA")
+            .unwrap()
+            .first_write(entry.etag);
+        block_on(client.save_state("statestore", &[item])).unwrap();
+
+        let sent = transport.sent();
+        assert_eq!(
+            sent[0].url,
+            "http://localhost:3500/v1.0/state/statestore/item-2"
+        );
+        assert_eq!(
+            sent[1].url,
+            "http://localhost:3500/v1.0/state/statestore/item-42"
+        );
+        assert_eq!(sent[2].method, Method::Post);
+        assert_eq!(sent[2].url, "http://localhost:3500/v1.0/state/statestore");
+        assert_eq!(
+            body_json(&sent[2]),
+            json!([{
+                "key": "item-2",
+                "value": "MOCHA",
+                "etag": "3",
+                "options": { "concurrency": "first-write" }
+            }])
+        );
+    }
+
+    #[test]
+    fn decodes_bulk_state_with_missing_keys() {
+        let (client, transport) = StubTransport::client([ok(json!([
+            { "key": "item-1", "data": "LATTE", "etag": "1" },
+            { "key": "item-42" }
+        ]))]);
+        let keys = vec!["item-1".to_string(), "item-42".to_string()];
+
+        let items = block_on(client.get_bulk_state::<String>("statestore", &keys)).unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].key, "item-1");
+        assert_eq!(items[0].data.as_deref(), Some("LATTE"));
+        assert_eq!(items[1].key, "item-42");
+        assert_eq!(items[1].data, None);
+        let sent = transport.sent();
+        assert_eq!(
+            sent[0].url,
+            "http://localhost:3500/v1.0/state/statestore/bulk"
+        );
+        assert_eq!(body_json(&sent[0]), json!({ "keys": keys }));
+    }
+
+    #[test]
+    fn maps_failures_to_typed_errors() {
+        let (client, _) =
+            StubTransport::client([status(404), status(409), ok(json!({ "not": "a list" }))]);
+
+        let err = block_on(client.get_state::<String>("statestore", "item-1")).unwrap_err();
+        assert!(
+            matches!(&err, DaprError::Status { status: 404, operation } if operation == "get state item-1"),
+            "{:?}",
+            err
+        );
+        assert!(!err.is_retriable());
+        assert!(!err.is_etag_mismatch());
+
+        let item = StateItem::new("item-1", "LATTE").unwrap().first_write(None);
+        let err = block_on(client.save_state("statestore", &[item])).unwrap_err();
+        assert!(err.is_etag_mismatch(), "{:?}", err);
+
+        let err = block_on(client.get_bulk_state::<String>("statestore", &[])).unwrap_err();
+        assert!(matches!(err, DaprError::Decode { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn retries_retriable_publishes_with_backoff() {
+        let (client, transport) = StubTransport::client([
+            status(503),
+            Some(Err(DaprError::Transport("connection refused".to_string()))),
+            status(204),
+        ]);
+        let client = client.with_publish_policy(PublishPolicy {
+            timeout: None,
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(100),
+        });
+
+        block_on(client.publish("orderpubsub", "orderup", &json!({}))).unwrap();
+
+        assert_eq!(transport.sent().len(), 3);
+        assert_eq!(
+            transport.slept(),
+            vec![Duration::from_millis(100), Duration::from_millis(200)]
+        );
+    }
+
+    #[test]
+    fn does_not_retry_rejected_publishes() {
+        let (client, transport) = StubTransport::client([status(400)]);
+        let client = client.with_publish_policy(PublishPolicy {
+            timeout: None,
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(100),
+        });
+
+        let err = block_on(client.publish("orderpubsub", "orderup", &json!({}))).unwrap_err();
+
+        assert!(
+            matches!(err, DaprError::Status { status: 400, .. }),
+            "{:?}",
+            err
+        );
+        assert_eq!(transport.sent().len(), 1);
+        assert!(transport.slept().is_empty());
+    }
+
+    #[test]
+    fn times_out_every_publish_attempt() {
+        let (client, transport) = StubTransport::client([None, None]);
+        let timeout = Duration::from_secs(2);
+        let client = client.with_publish_policy(PublishPolicy {
+            timeout: Some(timeout),
+            max_attempts: 2,
+            retry_delay: Duration::from_millis(100),
+        });
+
+        let err = block_on(client.publish("orderpubsub", "orderup", &json!({}))).unwrap_err();
+
+        assert!(
+            matches!(&err, DaprError::Timeout { operation, timeout: t } if operation == "publish orderup" && *t == timeout),
+            "{:?}",
+            err
+        );
+        assert_eq!(transport.sent().len(), 2);
+        assert_eq!(
+            transport.slept(),
+            vec![timeout, Duration::from_millis(100), timeout]
+        );
+    }
+}
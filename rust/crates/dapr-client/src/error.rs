@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DaprError {
+    #[error("could not reach Dapr: {0}")]
+    Transport(String),
+    #[error("Dapr answered {status} to {operation}")]
+    Status { operation: String, status: u16 },
+    #[error("{operation} timed out after {timeout:?}")]
+    Timeout { operation: String, timeout: Duration },
+    #[error("could not encode the request: {0}")]
+    Encode(#[source] serde_json::Error),
+    #[error("could not decode the response to {operation}: {source}")]
+    Decode {
+        operation: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl DaprError {
+    // worth another attempt: the sidecar or its broker may recover, a rejected request won't
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            DaprError::Transport(_) | DaprError::Timeout { .. } => true,
+            DaprError::Status { status, .. } => *status >= 500,
+            DaprError::Encode(_) | DaprError::Decode { .. } => false,
+        }
+    }
+
+    // a first-write save lost to another writer; read the state again and retry
+    pub fn is_etag_mismatch(&self) -> bool {
+        matches!(self, DaprError::Status { status: 409, .. })
+    }
+}
@@ -0,0 +1,23 @@
+//! Minimal client for the Dapr sidecar HTTP API, shared by the counter service and the Spin component.
+//!
+//! Only URL building, payload encoding and response handling live here; the HTTP call itself goes
+//! through a [`Transport`], so each caller plugs in the stack it already runs on.
+
+mod backoff;
+mod client;
+mod error;
+#[cfg(feature = "surf")]
+mod surf_transport;
+mod timeout;
+mod transport;
+
+pub use async_trait::async_trait;
+pub use backoff::{backoff_delay, MAX_RETRY_DELAY};
+pub use client::{
+    sidecar_url, BulkStateItem, Concurrency, DaprClient, PublishPolicy, StateEntry, StateItem,
+    StateOptions,
+};
+pub use error::DaprError;
+#[cfg(feature = "surf")]
+pub use surf_transport::SurfTransport;
+pub use transport::{DaprRequest, DaprResponse, Method, Transport};
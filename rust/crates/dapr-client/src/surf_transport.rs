@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::{
+    error::DaprError,
+    transport::{DaprRequest, DaprResponse, Method, Transport},
+};
+
+// Carries Dapr requests over a surf client, so they share its connection pool and timeout
+#[derive(Clone)]
+pub struct SurfTransport(pub surf::Client);
+
+#[async_trait]
+impl Transport for SurfTransport {
+    async fn send(&self, req: DaprRequest) -> Result<DaprResponse, DaprError> {
+        tracing::debug!("url: {}", req.url);
+
+        let method = match req.method {
+            Method::Get => surf::http::Method::Get,
+            Method::Post => surf::http::Method::Post,
+            Method::Put => surf::http::Method::Put,
+            Method::Delete => surf::http::Method::Delete,
+        };
+        let mut builder = self.0.request(method, &req.url);
+        for (name, value) in &req.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = req.body {
+            builder = builder.body_bytes(body);
+        }
+
+        let mut res = builder
+            .await
+            .map_err(|err| DaprError::Transport(err.to_string()))?;
+        let body = res
+            .body_bytes()
+            .await
+            .map_err(|err| DaprError::Transport(err.to_string()))?;
+
+        Ok(DaprResponse {
+            status: res.status().into(),
+            body,
+            etag: res.header("etag").map(|etag| etag.as_str().to_string()),
+        })
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
@@ -0,0 +1,27 @@
+use std::{
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+};
+
+// Runs `fut` until `timer` fires, `None` meaning the timer won. Written against plain futures so it
+// works on whatever executor the transport runs on.
+pub(crate) async fn with_timeout<F, S>(fut: F, timer: S) -> Option<F::Output>
+where
+    F: Future,
+    S: Future<Output = ()>,
+{
+    let mut fut = pin!(fut);
+    let mut timer = pin!(timer);
+
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        if timer.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    })
+    .await
+}
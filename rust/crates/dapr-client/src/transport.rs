@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::error::DaprError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub struct DaprRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DaprResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+    // the ETag header, which Dapr sets on state reads
+    pub etag: Option<String>,
+}
+
+impl DaprResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+// Sends a request to the sidecar. Futures only need to be Send off wasm, where Spin runs single-threaded.
+#[cfg_attr(target_arch = "wasm32", async_trait::async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait::async_trait)]
+pub trait Transport {
+    async fn send(&self, req: DaprRequest) -> Result<DaprResponse, DaprError>;
+
+    // the runtime's timer, used for publish timeouts and the pause between retries
+    async fn sleep(&self, duration: Duration);
+}
@@ -12,7 +12,14 @@ CREATE TABLE
         order_source integer NOT NULL,
         loyalty_member_id uuid NOT NULL,
         order_status integer NOT NULL,
-        updated timestamp
+        version integer NOT NULL DEFAULT 0,
+        created_at timestamp
+        with
+            time zone NOT NULL DEFAULT (now()),
+            updated_at timestamp
+        with
+            time zone NOT NULL DEFAULT (now()),
+            deleted_at timestamp
         with
             time zone NULL,
             CONSTRAINT pk_orders PRIMARY KEY (id)
@@ -27,12 +34,12 @@ CREATE TABLE
         item_status integer NOT NULL,
         is_barista_order boolean NOT NULL,
         order_id uuid NULL,
-        created timestamp
+        created_at timestamp
         with
             time zone NOT NULL DEFAULT (now()),
-            updated timestamp
+            updated_at timestamp
         with
-            time zone NULL,
+            time zone NOT NULL DEFAULT (now()),
             CONSTRAINT pk_line_items PRIMARY KEY (id),
             CONSTRAINT fk_line_items_orders_order_temp_id FOREIGN KEY (order_id) REFERENCES "order".orders (id)
     );
@@ -43,6 +50,21 @@ CREATE INDEX ix_line_items_order_id ON "order".line_items (order_id);
 
 CREATE UNIQUE INDEX ix_orders_id ON "order".orders (id);
 
+CREATE INDEX ix_orders_created_at ON "order".orders (created_at);
+
+CREATE INDEX ix_orders_loyalty_member_id ON "order".orders (loyalty_member_id);
+
+CREATE TABLE
+    "order".idempotency_keys (
+        key text NOT NULL,
+        order_id uuid NOT NULL,
+        created timestamp
+        with
+            time zone NOT NULL DEFAULT (now()),
+            CONSTRAINT pk_idempotency_keys PRIMARY KEY (key),
+            CONSTRAINT fk_idempotency_keys_orders_order_id FOREIGN KEY (order_id) REFERENCES "order".orders (id)
+    );
+
 COMMIT;
 
 --  BARISTA
@@ -1,5 +1,5 @@
 use std::time;
-use std::{env, time::Duration};
+use std::time::Duration;
 
 use chrono::serde::ts_seconds::deserialize as from_ts;
 use chrono::{prelude::*, serde::ts_seconds};
@@ -79,7 +79,6 @@ struct BaristaOrderUp {
 
 #[tokio::main]
 async fn main() {
-    env::set_var("RUST_LOG", "debug");
     dotenv::dotenv().ok();
 
     tracing_subscriber::registry()
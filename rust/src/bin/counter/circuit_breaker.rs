@@ -0,0 +1,106 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::prometheus::PRODUCT_CIRCUIT_STATE_METRIC;
+
+// Stops calling a dependency after `failure_threshold` failures in a row. Once `cooldown` has
+// passed a single probe is let through: success closes the circuit, failure opens it again.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    state: Arc<Mutex<State>>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // a probe that never reports back (e.g. its request timed out) is replaced after a cooldown
+    HalfOpen { probe_started: Instant },
+}
+
+impl State {
+    // exported on /metrics as 0 closed, 1 open, 2 half-open
+    fn metric_value(self) -> f64 {
+        match self {
+            State::Closed { .. } => 0.0,
+            State::Open { .. } => 1.0,
+            State::HalfOpen { .. } => 2.0,
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        let state = State::Closed { failures: 0 };
+        metrics::gauge!(PRODUCT_CIRCUIT_STATE_METRIC, state.metric_value());
+
+        CircuitBreaker {
+            state: Arc::new(Mutex::new(state)),
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        }
+    }
+
+    // Ok when a call may go ahead, otherwise how long until the next probe
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(until - now),
+            State::HalfOpen { probe_started } if now < probe_started + self.cooldown => {
+                Err(probe_started + self.cooldown - now)
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                tracing::info!("product service circuit half-open, probing");
+                self.transition(&mut state, State::HalfOpen { probe_started: now });
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Closed { .. }) {
+            tracing::info!("product service circuit closed");
+        }
+        self.transition(&mut state, State::Closed { failures: 0 });
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::HalfOpen { .. } => self.failure_threshold,
+            // a call started before the circuit opened, the cooldown already covers it
+            State::Open { .. } => return,
+        };
+
+        if failures < self.failure_threshold {
+            self.transition(&mut state, State::Closed { failures });
+            return;
+        }
+
+        tracing::warn!(
+            "product service circuit open for {:?} after {} consecutive failures",
+            self.cooldown,
+            failures
+        );
+        self.transition(
+            &mut state,
+            State::Open {
+                until: Instant::now() + self.cooldown,
+            },
+        );
+    }
+
+    fn transition(&self, state: &mut State, next: State) {
+        *state = next;
+        metrics::gauge!(PRODUCT_CIRCUIT_STATE_METRIC, next.metric_value());
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use config::{File, Value};
+
+// Finds `--config <path>` / `--config=<path>` or CONFIG_FILE before clap parses anything,
+// since the file has to be loaded first to sit below env vars and flags.
+pub fn config_file_path() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next();
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.to_string());
+        }
+    }
+
+    std::env::var("CONFIG_FILE").ok()
+}
+
+// Exposes every key in the file as the env var clap reads for it, unless that var is already set,
+// so flags > env > file > defaults. Keys are the snake_case Config field names, e.g. `app_port`.
+pub fn load_config_file(path: &str, known_keys: &HashSet<String>) -> Result<(), String> {
+    let settings = config::Config::builder()
+        .add_source(File::from(Path::new(path)))
+        .build()
+        .map_err(|err| err.to_string())?;
+    let entries = settings
+        .try_deserialize::<HashMap<String, Value>>()
+        .map_err(|err| err.to_string())?;
+
+    let mut unknown_keys = entries
+        .keys()
+        .filter(|key| !known_keys.contains(&key.replace('-', "_")))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !unknown_keys.is_empty() {
+        unknown_keys.sort();
+        return Err(format!("unknown keys: {}", unknown_keys.join(", ")));
+    }
+
+    for (key, value) in entries {
+        let env_name = key.replace('-', "_").to_uppercase();
+        if std::env::var_os(&env_name).is_some() {
+            continue;
+        }
+
+        let value = to_env_value(value).map_err(|err| format!("{}: {}", key, err))?;
+        std::env::set_var(env_name, value);
+    }
+
+    Ok(())
+}
+
+// lists become the comma-separated form the env vars already take, e.g. allowed_origins
+fn to_env_value(value: Value) -> Result<String, String> {
+    match value.clone().into_array() {
+        Ok(values) => values
+            .into_iter()
+            .map(|value| value.into_string().map_err(|err| err.to_string()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|values| values.join(",")),
+        Err(_) => value.into_string().map_err(|err| err.to_string()),
+    }
+}
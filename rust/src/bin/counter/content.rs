@@ -0,0 +1,150 @@
+use std::fmt;
+
+use axum::{
+    async_trait,
+    body::{Bytes, HttpBody},
+    extract::FromRequest,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{AppError, FieldError};
+
+const MSGPACK: &str = "application/msgpack";
+
+// Wire formats an order can be placed and returned in; JSON unless the client asks otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MsgPack,
+}
+
+impl Format {
+    // only an explicit application/msgpack in Accept switches the response over
+    pub fn from_accept(headers: &HeaderMap) -> Self {
+        let accepts_msgpack = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|accept| accept.split(','))
+            .any(|media_range| media_type(media_range) == MSGPACK);
+
+        if accepts_msgpack {
+            Format::MsgPack
+        } else {
+            Format::Json
+        }
+    }
+
+    pub fn respond<T: Serialize>(self, status: StatusCode, value: &T) -> Response {
+        match self {
+            Format::Json => (status, Json(value)).into_response(),
+            Format::MsgPack => match to_msgpack(value) {
+                Ok(body) => (
+                    status,
+                    [(header::CONTENT_TYPE, HeaderValue::from_static(MSGPACK))],
+                    body,
+                )
+                    .into_response(),
+                Err(err) => {
+                    tracing::error!("encoding msgpack response: {}", err);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            },
+        }
+    }
+}
+
+// Request body in whichever format its Content-Type names, 415 for anything but JSON or msgpack
+pub struct Negotiated<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Negotiated<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(media_type);
+
+        let decode: fn(&[u8]) -> Result<T, AppError> = match content_type.as_deref() {
+            Some("application/json") => from_json,
+            Some(MSGPACK) => from_msgpack,
+            _ => return Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Expected request with `Content-Type: application/json` or `application/msgpack`",
+            )
+                .into_response()),
+        };
+
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        decode(&body)
+            .map(Negotiated)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+// A body that fails to decode answers like any other invalid input, pointing at the field serde stopped at
+pub fn decode_error<E: fmt::Display>(err: serde_path_to_error::Error<E>) -> AppError {
+    let field = match err.path().to_string() {
+        path if path == "." => "body".to_string(),
+        path => path,
+    };
+    AppError::Validation(vec![FieldError::new(
+        field,
+        "invalid_body",
+        err.inner().to_string(),
+    )])
+}
+
+fn media_type(media_range: &str) -> String {
+    media_range
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+// human-readable so ids, prices and timestamps keep the string forms they have in JSON
+fn to_msgpack<T: Serialize>(value: &T) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let mut body = Vec::new();
+    value.serialize(
+        &mut rmp_serde::Serializer::new(&mut body)
+            .with_struct_map()
+            .with_human_readable(),
+    )?;
+    Ok(body)
+}
+
+fn from_msgpack<T: DeserializeOwned>(body: &[u8]) -> Result<T, AppError> {
+    serde_path_to_error::deserialize(&mut rmp_serde::Deserializer::new(body).with_human_readable())
+        .map_err(decode_error)
+}
+
+fn from_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, AppError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(decode_error)?;
+    // trailing bytes after the document are as malformed as a broken one
+    deserializer.end().map_err(|err| {
+        AppError::Validation(vec![FieldError::new(
+            "body",
+            "invalid_body",
+            err.to_string(),
+        )])
+    })?;
+    Ok(value)
+}
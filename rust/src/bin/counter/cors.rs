@@ -0,0 +1,57 @@
+use axum::{
+    http::{header, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+// `allowed_origins` is a comma-separated list of origins, or `*` to allow any origin
+pub fn cors_layer(allowed_origins: &str) -> CorsLayer {
+    let allow_origin: AllowOrigin = if allowed_origins.trim() == "*" {
+        Any.into()
+    } else {
+        AllowOrigin::list(
+            allowed_origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .filter_map(|origin| match HeaderValue::from_str(origin) {
+                    Ok(value) => Some(value),
+                    Err(_) => {
+                        tracing::warn!("ignoring invalid CORS origin '{}'", origin);
+                        None
+                    }
+                }),
+        )
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+        ])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            HeaderName::from_static("idempotency-key"),
+        ])
+        .expose_headers([HeaderName::from_static("x-total-count")])
+}
+
+// Answers CORS preflight requests with 204 No Content rather than an empty 200
+pub async fn preflight_no_content<B>(req: Request<B>, next: Next<B>) -> Response {
+    let is_preflight = req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    let mut res = next.run(req).await;
+    if is_preflight && res.status() == StatusCode::OK {
+        *res.status_mut() = StatusCode::NO_CONTENT;
+    }
+
+    res
+}
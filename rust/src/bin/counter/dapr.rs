@@ -0,0 +1,3 @@
+pub use dapr_client::SurfTransport;
+
+pub type DaprClient = dapr_client::DaprClient<SurfTransport>;
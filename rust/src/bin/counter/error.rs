@@ -0,0 +1,97 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use dapr_client::DaprError;
+use sea_orm::DbErr;
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("database error: {0}")]
+    Db(#[from] DbErr),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("bad request: {0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    UnprocessableEntity(String),
+    #[error("validation failed: {}", field_messages(.0))]
+    Validation(Vec<FieldError>),
+    #[error("product service error: {0}")]
+    ProductService(String),
+    #[error("{0}")]
+    ServiceUnavailable(String),
+    #[error("dapr error: {0}")]
+    Dapr(#[from] DaprError),
+}
+
+// One rejected input; `field` is the JSON path in the request body, `code` a stable key front-ends can translate
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    #[schema(example = "baristaItems[0].itemType")]
+    pub field: String,
+    #[schema(example = "unknown_item_type")]
+    pub code: String,
+    #[schema(example = "unknown item type 42")]
+    pub message: String,
+}
+
+// Body of every 422 answered for invalid input
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationErrors {
+    pub errors: Vec<FieldError>,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, code: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+fn field_messages(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{}: {}", error.field, error.message))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if let AppError::Validation(errors) = self {
+            tracing::warn!("validation failed: {}", field_messages(&errors));
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(ValidationErrors { errors }),
+            )
+                .into_response();
+        }
+
+        let status = match self {
+            AppError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::UnprocessableEntity(_) | AppError::Validation(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::Dapr(DaprError::Timeout { .. }) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::ProductService(_) | AppError::Dapr(_) => StatusCode::BAD_GATEWAY,
+            AppError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+
+        tracing::error!("{}", self);
+
+        (status, self.to_string()).into_response()
+    }
+}
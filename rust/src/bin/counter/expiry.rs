@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use counter_entity::orders;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QuerySelect};
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use crate::{cancel_order, error::AppError, status::OrderStatus, AppState};
+
+// Cancels orders still unfinished `max_age` after they were placed, sweeping every `interval`.
+// `app` should carry a connection of its own, so sweeps never take one a request is waiting for.
+pub fn spawn_order_expiry(app: AppState, interval: Duration, max_age: Duration) {
+    tracing::info!(
+        "expiring orders unfinished after {:?}, sweeping every {:?}",
+        max_age,
+        interval
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match expire_stale_orders(&app, max_age).await {
+                Ok(expired) => tracing::info!("order expiry sweep cancelled {} orders", expired),
+                Err(err) => tracing::error!("order expiry sweep failed: {}", err),
+            }
+        }
+    });
+}
+
+async fn expire_stale_orders(app: &AppState, max_age: Duration) -> Result<usize, AppError> {
+    // a threshold too large to subtract from now can't have been reached yet
+    let Some(cutoff) = chrono::Duration::from_std(max_age)
+        .ok()
+        .and_then(|max_age| Utc::now().checked_sub_signed(max_age))
+    else {
+        return Ok(0);
+    };
+    let stale_ids = orders::Entity::find()
+        .select_only()
+        .column(orders::Column::Id)
+        .filter(orders::Column::DeletedAt.is_null())
+        .filter(orders::Column::OrderStatus.is_in([
+            i32::from(OrderStatus::Placed),
+            i32::from(OrderStatus::InProgress),
+        ]))
+        .filter(orders::Column::CreatedAt.lt(cutoff))
+        .into_tuple::<Uuid>()
+        .all(&app.db_conn)
+        .await?;
+
+    let mut expired = 0;
+    for id in stale_ids {
+        match cancel_order(app, id).await {
+            Ok(_) => {
+                tracing::debug!("expired order {}", id);
+                expired += 1;
+            }
+            // finished or removed since the query ran
+            Err(AppError::Conflict(_)) | Err(AppError::NotFound(_)) => {}
+            Err(err) => tracing::warn!("expiring order {}: {}", id, err),
+        }
+    }
+
+    Ok(expired)
+}
@@ -0,0 +1,839 @@
+// End-to-end tests over the full router, with the product service and the Dapr sidecar mocked.
+// Tests touching the database are ignored by default; they run against a fresh database on
+// TEST_DATABASE_URL when it is set, else against a Postgres container (Docker required):
+//   TEST_DATABASE_URL=postgres://postgres@localhost/postgres cargo test -- --ignored
+use axum::{body::Body, http::Request, Router};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use sea_orm::{ConnectionTrait, Statement};
+use serde_json::Value;
+use testcontainers_modules::{
+    postgres::Postgres,
+    testcontainers::{runners::AsyncRunner, ContainerAsync},
+};
+use tower::ServiceExt;
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::*;
+
+pub(crate) const ITEMS_BY_TYPES_PATH: &str = "^/v1/api/items-by-types/";
+
+pub(crate) struct TestApp {
+    pub router: Router,
+    pub db_conn: DatabaseConnection,
+    pub product: MockServer,
+    pub dapr: MockServer,
+    database: Option<TestDatabase>,
+}
+
+enum TestDatabase {
+    // dropped again once the test is done with it
+    Scratch {
+        admin_url: String,
+        name: String,
+    },
+    // held so the container is only removed once the test is done
+    Container {
+        _container: Box<ContainerAsync<Postgres>>,
+    },
+}
+
+impl TestApp {
+    // a fresh, migrated database behind the app
+    pub async fn spawn() -> TestApp {
+        let (database_url, database) = match std::env::var("TEST_DATABASE_URL") {
+            Ok(admin_url) => {
+                let name = format!("counter_test_{}", Uuid::new_v4().simple());
+                execute(&admin_url, &format!("CREATE DATABASE \"{}\"", name)).await;
+                (
+                    with_database_name(&admin_url, &name),
+                    TestDatabase::Scratch { admin_url, name },
+                )
+            }
+            Err(_) => {
+                let container = Postgres::default()
+                    .start()
+                    .await
+                    .expect("Postgres container start failed");
+                let database_url = format!(
+                    "postgres://postgres:postgres@{}:{}/postgres",
+                    container.get_host().await.expect("container host"),
+                    container
+                        .get_host_port_ipv4(5432)
+                        .await
+                        .expect("container port")
+                );
+                let container = Box::new(container);
+                (
+                    database_url,
+                    TestDatabase::Container {
+                        _container: container,
+                    },
+                )
+            }
+        };
+
+        let db_conn = Database::connect(database_url.as_str())
+            .await
+            .expect("Database connection failed");
+        Migrator::up(&db_conn, None)
+            .await
+            .expect("Database migration failed");
+
+        TestApp::with_db(db_conn, Some(database)).await
+    }
+
+    // for requests answered before the database is reached
+    pub async fn without_db(configure: impl FnOnce(&mut Config)) -> TestApp {
+        TestApp::with_config(DatabaseConnection::Disconnected, None, configure).await
+    }
+
+    async fn with_db(db_conn: DatabaseConnection, database: Option<TestDatabase>) -> TestApp {
+        TestApp::with_config(db_conn, database, |_| {}).await
+    }
+
+    async fn with_config(
+        db_conn: DatabaseConnection,
+        database: Option<TestDatabase>,
+        configure: impl FnOnce(&mut Config),
+    ) -> TestApp {
+        let product = MockServer::start().await;
+        let dapr = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path_regex("^/v1.0/publish/"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&dapr)
+            .await;
+
+        let mut config = Config::parse_from(["counter_api"]);
+        config.product_url = Some(product.uri());
+        config.dapr_url = dapr.uri();
+        config.disable_price_cache = true;
+        config.product_retry_delay_ms = 0;
+        configure(&mut config);
+        config.api_base_path = normalize_base_path(&config.api_base_path);
+
+        let http_client = surf::Client::new();
+        let state = AppState {
+            db_conn: db_conn.clone(),
+            dapr_client: DaprClient::new(SurfTransport(http_client.clone()), &config.dapr_url),
+            product_client: ProductClient::new(
+                http_client.clone(),
+                &product.uri(),
+                config.product_max_attempts,
+                Duration::from_millis(config.product_retry_delay_ms),
+                config.product_max_concurrency,
+            )
+            .with_circuit_breaker(CircuitBreaker::new(
+                config.product_breaker_threshold,
+                Duration::from_secs(config.product_breaker_cooldown),
+            )),
+            metrics_handle: PrometheusBuilder::new().build_recorder().handle(),
+            webhooks: WebhookNotifier::disabled(http_client),
+            order_events: OrderEvents::default(),
+            ready_checks: Arc::new([]),
+            order_schema: config
+                .validate_order_schema
+                .then(|| OrderSchema::compile().expect("order schema compiles")),
+            config,
+        };
+
+        TestApp {
+            router: app_router(state, InFlight::default()),
+            db_conn,
+            product,
+            dapr,
+            database,
+        }
+    }
+
+    // a product service pricing every item type it is asked for at `price`
+    pub async fn mock_prices(&self, price: f32) {
+        Mock::given(method("GET"))
+            .and(path_regex(ITEMS_BY_TYPES_PATH))
+            .respond_with(move |request: &wiremock::Request| {
+                let items = request
+                    .url
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|item_type| !item_type.is_empty())
+                    .map(|item_type| {
+                        json!({ "price": price, "itemType": item_type.parse::<i32>().unwrap() })
+                    })
+                    .collect::<Vec<_>>();
+                ResponseTemplate::new(200).set_body_json(items)
+            })
+            .mount(&self.product)
+            .await;
+    }
+
+    pub async fn request(&self, request: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router is infallible");
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("response body");
+        // plain-text bodies, such as axum's own rejections, come back as a JSON string
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()))
+        };
+        (status, headers, body)
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, HeaderMap, Value) {
+        self.request(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn delete(&self, uri: &str) -> (StatusCode, HeaderMap, Value) {
+        self.request(Request::delete(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn post_json(&self, uri: &str, body: Value) -> (StatusCode, HeaderMap, Value) {
+        self.request(
+            Request::post(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    // paths the sidecar was asked to publish to, in order
+    pub async fn published_topics(&self) -> Vec<String> {
+        self.dapr
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .map(|request| request.url.path().to_string())
+            .collect()
+    }
+
+    pub async fn close(self) {
+        let _ = self.db_conn.close().await;
+        if let Some(TestDatabase::Scratch { admin_url, name }) = self.database {
+            execute(
+                &admin_url,
+                &format!("DROP DATABASE \"{}\" WITH (FORCE)", name),
+            )
+            .await;
+        }
+    }
+}
+
+async fn execute(url: &str, sql: &str) {
+    let db_conn = Database::connect(url)
+        .await
+        .expect("admin database connection failed");
+    db_conn
+        .execute(Statement::from_string(
+            db_conn.get_database_backend(),
+            sql.to_string(),
+        ))
+        .await
+        .unwrap_or_else(|err| panic!("{}: {}", sql, err));
+    let _ = db_conn.close().await;
+}
+
+// the same server and credentials, another database
+fn with_database_name(url: &str, name: &str) -> String {
+    let (url, query) = url
+        .split_once('?')
+        .map_or((url, None), |(url, query)| (url, Some(query)));
+    let base = url.rsplit_once('/').map_or(url, |(base, _)| base);
+    match query {
+        Some(query) => format!("{}/{}?{}", base, name, query),
+        None => format!("{}/{}", base, name),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn places_an_order_and_lists_it() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(3.25).await;
+
+    let (status, headers, placed) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({
+                "orderSource": 1,
+                "baristaItems": [{ "itemType": 0 }],
+                "kitchenItems": [{ "itemType": 7 }],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", placed);
+    let id = placed["id"].as_str().expect("order id").to_string();
+    assert_eq!(
+        headers[header::LOCATION].to_str().unwrap(),
+        format!("/v1/api/orders/{}", id)
+    );
+    assert_eq!(placed["orderSource"], 1);
+    assert_eq!(placed["total"], "6.50");
+    assert_eq!(placed["orderLines"].as_array().unwrap().len(), 2);
+
+    let id = Uuid::parse_str(&id).unwrap();
+    let order = Order::find_by_id(id)
+        .one(&app.db_conn)
+        .await
+        .unwrap()
+        .expect("order row");
+    assert_eq!(order.order_source, 1);
+    let lines = line_items::Entity::find()
+        .filter(line_items::Column::OrderId.eq(id))
+        .all(&app.db_conn)
+        .await
+        .unwrap();
+    let mut lines = lines
+        .iter()
+        .map(|line| (line.item_type, line.is_barista_order, line.price))
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(
+        lines,
+        vec![
+            (0, true, Decimal::new(325, 2)),
+            (7, false, Decimal::new(325, 2))
+        ]
+    );
+
+    let topics = app.published_topics().await;
+    assert!(
+        topics.iter().any(|topic| topic.ends_with("/orderPlaced")),
+        "{:?}",
+        topics
+    );
+
+    let (status, _, page) = app.get("/v1/api/fulfillment-orders").await;
+    assert_eq!(status, StatusCode::OK, "{}", page);
+    assert_eq!(page["page"], 0);
+    let items = page["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["id"], id.to_string());
+    assert_eq!(items[0]["total"], "6.50");
+    assert_eq!(items[0]["statusName"], placed["statusName"]);
+    let mut item_types = items[0]["orderLines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|line| line["itemType"].as_i64().unwrap())
+        .collect::<Vec<_>>();
+    item_types.sort();
+    assert_eq!(item_types, vec![0, 7]);
+
+    app.close().await;
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn lists_no_orders_on_an_empty_database() {
+    let app = TestApp::spawn().await;
+
+    let (status, _, page) = app.get("/v1/api/fulfillment-orders").await;
+    assert_eq!(status, StatusCode::OK, "{}", page);
+    assert_eq!(page["items"], json!([]));
+    assert_eq!(page["totalPages"], 0);
+
+    app.close().await;
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn answers_500_when_listing_orders_fails() {
+    let app = TestApp::spawn().await;
+    // a closed pool fails every query, which must not pass for an empty page; `Disconnected`
+    // can't stand in for it, sea-orm panics building the query
+    app.db_conn.clone().close().await.unwrap();
+
+    let (status, _, body) = app.get("/v1/api/fulfillment-orders").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{}", body);
+    assert!(body.get("items").is_none(), "{}", body);
+
+    app.close().await;
+}
+
+// (pubsub, topic) the sidecar delivers to each route, relative to the base path
+const SUBSCRIPTIONS: &[(&str, &str, &str)] = &[
+    (
+        "baristaorderuppubsub",
+        "baristaorderup",
+        "update-barista-order-line-item",
+    ),
+    (
+        "kitchenorderuppubsub",
+        "kitchenorderup",
+        "update-kitchen-order-line-item",
+    ),
+    ("pubsub", "ponged", "ponged"),
+];
+
+// what Dapr reads from /dapr/subscribe, so a misspelt or extra field fails the test
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Subscription {
+    pubsubname: String,
+    topic: String,
+    route: String,
+}
+
+async fn assert_subscriptions(app: &TestApp, route_prefix: &str) {
+    let (status, _, body) = app.get("/dapr/subscribe").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let subscriptions = serde_json::from_value::<Vec<Subscription>>(body).unwrap();
+
+    let expected = SUBSCRIPTIONS
+        .iter()
+        .map(|(pubsub, topic, route)| Subscription {
+            pubsubname: pubsub.to_string(),
+            topic: topic.to_string(),
+            route: format!("{}{}", route_prefix, route),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(subscriptions, expected);
+
+    // Dapr resolves a relative route against the app root and posts the event there
+    for Subscription { route, .. } in subscriptions {
+        let uri = format!("/{}", route.trim_start_matches('/'));
+        let (status, _, body) = app.post_json(&uri, json!({})).await;
+        assert!(
+            status != StatusCode::NOT_FOUND && status != StatusCode::METHOD_NOT_ALLOWED,
+            "POST {} answered {}: {}",
+            uri,
+            status,
+            body
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribes_to_routes_it_serves() {
+    let app = TestApp::without_db(|_| {}).await;
+
+    assert_subscriptions(&app, "").await;
+}
+
+#[tokio::test]
+async fn subscribes_to_routes_under_the_base_path() {
+    let app = TestApp::without_db(|config| config.api_base_path = "counter/".to_string()).await;
+
+    assert_subscriptions(&app, "/counter/").await;
+    let (status, _, _) = app.post_json("/ponged", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn places_a_kitchen_only_order() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(2.5).await;
+
+    let (status, _, placed) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "kitchenItems": [{ "itemType": 0 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", placed);
+    let order_lines = placed["orderLines"].as_array().unwrap();
+    assert_eq!(order_lines.len(), 1);
+    assert_eq!(order_lines[0]["itemType"], 0);
+    assert_eq!(order_lines[0]["isBaristaOrder"], false);
+
+    let id = Uuid::parse_str(placed["id"].as_str().unwrap()).unwrap();
+    let lines = line_items::Entity::find()
+        .filter(line_items::Column::OrderId.eq(id))
+        .all(&app.db_conn)
+        .await
+        .unwrap();
+    assert_eq!(lines.len(), 1);
+    assert!(!lines[0].is_barista_order);
+
+    let topics = app.published_topics().await;
+    assert!(topics.contains(&"/v1.0/publish/kitchenpubsub/kitchenordered".to_string()));
+    assert!(!topics
+        .iter()
+        .any(|topic| topic.ends_with("/baristaordered")));
+
+    app.close().await;
+}
+
+#[tokio::test]
+async fn answers_502_when_the_product_service_fails() {
+    let app = TestApp::without_db(|config| config.product_max_attempts = 2).await;
+    Mock::given(method("GET"))
+        .and(path_regex(ITEMS_BY_TYPES_PATH))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.product)
+        .await;
+
+    let (status, _, body) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "baristaItems": [{ "itemType": 0 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+
+    // every attempt was made, and nothing went out for an order that was never saved
+    assert_eq!(app.product.received_requests().await.unwrap().len(), 2);
+    assert!(app.published_topics().await.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn replays_an_order_placed_with_the_same_idempotency_key() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(3.25).await;
+
+    let place = || {
+        Request::post("/v1/api/orders")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(IDEMPOTENCY_KEY_HEADER, "order-1")
+            .body(Body::from(
+                json!({ "baristaItems": [{ "itemType": 0 }] }).to_string(),
+            ))
+            .unwrap()
+    };
+    let (status, _, first) = app.request(place()).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", first);
+    let (status, _, replayed) = app.request(place()).await;
+    assert_eq!(status, StatusCode::OK, "{}", replayed);
+    assert_eq!(replayed["id"], first["id"]);
+    // replayed before pricing, so the product service is only asked once
+    assert_eq!(app.product.received_requests().await.unwrap().len(), 1);
+
+    assert_eq!(Order::find().count(&app.db_conn).await.unwrap(), 1);
+    assert_eq!(
+        line_items::Entity::find()
+            .count(&app.db_conn)
+            .await
+            .unwrap(),
+        1
+    );
+
+    app.close().await;
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn soft_deletes_an_order() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(3.25).await;
+    let (status, _, placed) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "baristaItems": [{ "itemType": 0 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", placed);
+    let id = placed["id"].as_str().unwrap().to_string();
+    let uri = format!("/v1/api/orders/{}", id);
+
+    let (status, _, body) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{}", body);
+
+    // the rows stay for audits, only marked deleted
+    let order = Order::find_by_id(Uuid::parse_str(&id).unwrap())
+        .one(&app.db_conn)
+        .await
+        .unwrap()
+        .expect("order row kept");
+    assert!(order.deleted_at.is_some());
+    assert_eq!(
+        line_items::Entity::find()
+            .count(&app.db_conn)
+            .await
+            .unwrap(),
+        1
+    );
+
+    let (status, _, _) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, page) = app.get("/v1/api/fulfillment-orders").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["items"], json!([]));
+
+    let (status, _, deleted) = app.get(&format!("{}?include_deleted=true", uri)).await;
+    assert_eq!(status, StatusCode::OK, "{}", deleted);
+    assert_eq!(deleted["id"], id.as_str());
+    assert!(deleted["deletedAt"].is_string(), "{}", deleted);
+
+    // already deleted, so there is nothing left to delete
+    let (status, _, _) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    app.close().await;
+}
+
+#[tokio::test]
+async fn answers_422_when_the_product_price_is_not_a_decimal() {
+    let app = TestApp::without_db(|_| {}).await;
+    // JSON has no NaN, but 1e39 overflows the f32 price to infinity
+    Mock::given(method("GET"))
+        .and(path_regex(ITEMS_BY_TYPES_PATH))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw(r#"[{"price":1e39,"itemType":0}]"#, "application/json"),
+        )
+        .mount(&app.product)
+        .await;
+
+    let (status, _, body) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "baristaItems": [{ "itemType": 0 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    assert!(
+        body.to_string().contains("invalid price for item type 0"),
+        "{}",
+        body
+    );
+    assert!(app.published_topics().await.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn prices_repeated_item_types_once() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(3.25).await;
+
+    let (status, _, placed) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "baristaItems": [{ "itemType": 1 }, { "itemType": 1 }, { "itemType": 1 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", placed);
+    assert_eq!(placed["total"], "9.75");
+
+    let lookups = app.product.received_requests().await.unwrap();
+    assert_eq!(lookups.len(), 1);
+    assert_eq!(lookups[0].url.path(), "/v1/api/items-by-types/1");
+
+    let id = Uuid::parse_str(placed["id"].as_str().unwrap()).unwrap();
+    let lines = line_items::Entity::find()
+        .filter(line_items::Column::OrderId.eq(id))
+        .all(&app.db_conn)
+        .await
+        .unwrap();
+    assert_eq!(lines.len(), 3);
+    assert!(lines
+        .iter()
+        .all(|line| line.item_type == 1 && line.price == Decimal::new(325, 2)));
+
+    app.close().await;
+}
+
+// a barista or kitchen order-up delivery for line `item_line_id`, in Dapr's CloudEvents envelope
+fn order_up_event(order_id: &Value, item_line_id: &Value) -> Value {
+    json!({
+        "specversion": "1.0",
+        "id": Uuid::new_v4().to_string(),
+        "source": "barista",
+        "type": "com.dapr.event.sent",
+        "datacontenttype": "application/json",
+        "data": {
+            "orderId": order_id,
+            "itemLineId": item_line_id,
+            "name": "ITEM",
+            "itemType": 0,
+            "timeIn": 1_700_000_000,
+            "madeBy": "test",
+            "timeUp": 1_700_000_060,
+        },
+    })
+}
+
+// the id of the order's line for the barista (or kitchen) station
+fn station_line_id(order: &Value, is_barista_order: bool) -> Value {
+    order["orderLines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|line| line["isBaristaOrder"] == is_barista_order)
+        .expect("line for the station")["id"]
+        .clone()
+}
+
+async fn line_statuses(app: &TestApp, order_id: &Value) -> Vec<(bool, i32)> {
+    let id = Uuid::parse_str(order_id.as_str().unwrap()).unwrap();
+    let mut statuses = line_items::Entity::find()
+        .filter(line_items::Column::OrderId.eq(id))
+        .all(&app.db_conn)
+        .await
+        .unwrap()
+        .iter()
+        .map(|line| (line.is_barista_order, line.item_status))
+        .collect::<Vec<_>>();
+    statuses.sort();
+    statuses
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn fulfills_only_the_line_named_by_the_order_up() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(3.25).await;
+    let (status, _, placed) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "baristaItems": [{ "itemType": 0 }], "kitchenItems": [{ "itemType": 7 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", placed);
+    let order_id = &placed["id"];
+    let placed_statuses = line_statuses(&app, order_id).await;
+
+    // the kitchen line named on the barista topic, or a line nobody has, changes nothing
+    for line_id in [station_line_id(&placed, false), json!(Uuid::new_v4())] {
+        let (status, _, ack) = app
+            .post_json(
+                "/update-barista-order-line-item",
+                order_up_event(order_id, &line_id),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", ack);
+        assert_eq!(ack["status"], "DROP");
+        assert_eq!(line_statuses(&app, order_id).await, placed_statuses);
+    }
+
+    let (status, _, ack) = app
+        .post_json(
+            "/update-barista-order-line-item",
+            order_up_event(order_id, &station_line_id(&placed, true)),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", ack);
+    assert_eq!(ack["status"], "SUCCESS");
+    let fulfilled = i32::from(OrderStatus::Fulfilled);
+    let kitchen_status = placed_statuses[0].1;
+    assert_eq!(
+        line_statuses(&app, order_id).await,
+        vec![(false, kitchen_status), (true, fulfilled)]
+    );
+
+    app.close().await;
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn drops_order_ups_for_a_cancelled_order() {
+    let app = TestApp::spawn().await;
+    app.mock_prices(3.25).await;
+    let (status, _, placed) = app
+        .post_json(
+            "/v1/api/orders",
+            json!({ "baristaItems": [{ "itemType": 0 }], "kitchenItems": [{ "itemType": 7 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", placed);
+    let order_id = &placed["id"];
+    let uri = format!("/v1/api/orders/{}", order_id.as_str().unwrap());
+
+    let (status, _, cancelled) = app.post_json(&format!("{}/cancel", uri), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", cancelled);
+    let (_, _, before) = app.get(&uri).await;
+
+    for (route, is_barista_order) in [
+        ("/update-barista-order-line-item", true),
+        ("/update-kitchen-order-line-item", false),
+    ] {
+        let (status, _, ack) = app
+            .post_json(
+                route,
+                order_up_event(order_id, &station_line_id(&placed, is_barista_order)),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", ack);
+        assert_eq!(ack["status"], "DROP");
+    }
+    let (status, _, ack) = app
+        .post_json(
+            "/ponged",
+            json!({
+                "specversion": "1.0",
+                "id": Uuid::new_v4().to_string(),
+                "source": "spin",
+                "type": "com.dapr.event.sent",
+                "datacontenttype": "application/json",
+                "data": { "id": station_line_id(&placed, true) },
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", ack);
+    assert_eq!(ack["status"], "DROP");
+
+    // still cancelled, at the same version, with every line cancelled
+    let (_, _, after) = app.get(&uri).await;
+    assert_eq!(after["statusName"], "CANCELLED");
+    assert_eq!(after["version"], before["version"]);
+    let cancelled = i32::from(OrderStatus::Cancelled);
+    assert_eq!(
+        line_statuses(&app, order_id).await,
+        vec![(false, cancelled), (true, cancelled)]
+    );
+    // only the cancellation was announced, never a fulfilment
+    let status_changes = app
+        .published_topics()
+        .await
+        .into_iter()
+        .filter(|topic| topic.ends_with("/orderStatusChanged"))
+        .count();
+    assert_eq!(status_changes, 1);
+
+    app.close().await;
+}
+
+#[tokio::test]
+async fn rejects_item_types_off_the_menu() {
+    for validate_order_schema in [false, true] {
+        let app =
+            TestApp::without_db(|config| config.validate_order_schema = validate_order_schema)
+                .await;
+
+        let (status, _, body) = app
+            .post_json(
+                "/v1/api/orders",
+                json!({ "baristaItems": [{ "itemType": 0 }, { "itemType": 12 }] }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        let error = &body["errors"][0];
+        assert_eq!(error["field"], "baristaItems[1].itemType", "{}", body);
+        assert!(
+            error["message"].as_str().unwrap().contains("12"),
+            "{}",
+            body
+        );
+        assert!(app.product.received_requests().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn documents_the_menu_range() {
+    let app = TestApp::without_db(|_| {}).await;
+
+    let (status, _, api_doc) = app.get("/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    let item_type = &api_doc["components"]["schemas"]["ItemType"];
+    assert_eq!(item_type["minimum"], 0.0);
+    assert_eq!(item_type["maximum"], f64::from(item_type::MAX_ITEM_TYPE));
+}
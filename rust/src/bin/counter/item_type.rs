@@ -0,0 +1,58 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
+    ToSchema,
+};
+
+// the menu runs from CAPPUCCINO (0) to CROISSANT_CHOCOLATE (9)
+pub const MAX_ITEM_TYPE: i32 = 9;
+
+// Item type code on the menu; anything else is rejected when converted or deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "i32", into = "i32")]
+pub struct ItemType(i32);
+
+#[derive(Debug, Error)]
+#[error("item type {0} is not on the menu (0..={MAX_ITEM_TYPE})")]
+pub struct InvalidItemType(pub i32);
+
+impl TryFrom<i32> for ItemType {
+    type Error = InvalidItemType;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        if (0..=MAX_ITEM_TYPE).contains(&value) {
+            Ok(ItemType(value))
+        } else {
+            Err(InvalidItemType(value))
+        }
+    }
+}
+
+impl From<ItemType> for i32 {
+    fn from(item_type: ItemType) -> Self {
+        item_type.0
+    }
+}
+
+impl<'s> ToSchema<'s> for ItemType {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "ItemType",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::Integer)
+                .description(Some("Menu item, CAPPUCCINO (0) to CROISSANT_CHOCOLATE (9)"))
+                .minimum(Some(0.0))
+                .maximum(Some(f64::from(MAX_ITEM_TYPE)))
+                .into(),
+        )
+    }
+}
+
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -1,31 +1,114 @@
-use std::{env, time::Duration};
+mod circuit_breaker;
+mod config_file;
+mod content;
+mod cors;
+mod dapr;
+mod error;
+mod expiry;
+mod item_type;
+mod money;
+mod offline_pricing;
+mod openapi;
+mod order_events;
+mod order_schema;
+mod price_cache;
+mod product;
+mod prometheus;
+mod rate_limit;
+mod ready;
+mod shutdown;
+mod status;
+mod telemetry;
+mod webhook;
+
+#[cfg(test)]
+mod integration_tests;
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    convert::Infallible,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
 
 use chrono::serde::ts_seconds::deserialize as from_ts;
 use chrono::{prelude::*, serde::ts_seconds};
 
 use axum::{
+    body::{boxed, Body, Bytes},
     error_handling::HandleErrorLayer,
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware,
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post, put},
     Json, Router,
 };
-use clap::Parser;
-use cloudevents::Event;
-use counter_entity::{line_items, orders, orders::Entity as Order};
+use axum_server::tls_rustls::RustlsConfig;
+use clap::{CommandFactory, Parser, ValueEnum};
+use cloudevents::{AttributesReader, Event};
+use counter_entity::{idempotency_keys, line_items, orders, orders::Entity as Order};
+use counter_migration::{Migrator, MigratorTrait};
+use dapr_client::{sidecar_url, DaprError, PublishPolicy};
+use futures::{Stream, StreamExt};
+use http_body::Limited;
+use metrics_exporter_prometheus::PrometheusHandle;
 use sea_orm::{
-    prelude::Decimal, ActiveModelTrait, Database, DatabaseConnection, EntityTrait, ModelTrait, Set,
+    prelude::Decimal, sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectOptions,
+    ConnectionTrait, Database, DatabaseConnection, DbErr, EntityTrait, LoaderTrait, ModelTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, RuntimeErr, Select, Set,
     TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tokio::sync::Notify;
 use tower::{BoxError, ServiceBuilder};
-use tower_http::trace::{self, TraceLayer};
-use tracing::Level;
+use tower_http::{
+    compression::{
+        predicate::{NotForContentType, Predicate, SizeAbove},
+        CompressionLayer,
+    },
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::{self, TraceLayer},
+};
+use tracing::{Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
+use utoipa::{openapi::Server, IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 use uuid::Uuid;
 
+use circuit_breaker::CircuitBreaker;
+use config_file::{config_file_path, load_config_file};
+use content::Format;
+use cors::{cors_layer, preflight_no_content};
+use dapr::{DaprClient, SurfTransport};
+use error::{AppError, FieldError};
+use expiry::spawn_order_expiry;
+use item_type::ItemType;
+use openapi::ApiDoc;
+use order_events::OrderEvents;
+use order_schema::{OrderSchema, SchemaValidated};
+use price_cache::PriceCache;
+use product::{ItemTypeDto, ProductClient};
+use prometheus::{
+    setup_metrics_recorder, track_metrics, ORDERS_PLACED_METRIC, ORDER_LINE_ITEMS_METRIC,
+};
+use rate_limit::{rate_limit, RateLimiter};
+use ready::{parse_ready_checks, probe, ReadyCheck};
+use shutdown::{shutdown_signal, track_in_flight, InFlight};
+use status::{status_name, OrderStatus};
+use telemetry::{init_local_tracer, init_tracer, remote_context, shutdown_tracer};
+use webhook::{OrderStatusChanged, WebhookNotifier};
+
 // App config
 #[derive(Debug, Parser, Clone)]
 struct Config {
@@ -35,23 +118,210 @@ struct Config {
     app_port: u16,
     #[clap(default_value = "postgres://localhost/db", env)]
     database_url: String,
+    #[clap(default_value = "20", env)]
+    db_max_connections: u32,
+    #[clap(default_value = "1", env)]
+    db_min_connections: u32,
+    // seconds
+    #[clap(default_value = "8", env)]
+    db_connect_timeout: u64,
+    // seconds
+    #[clap(default_value = "600", env)]
+    db_idle_timeout: u64,
+    // Postgres may still be starting when the service is scheduled next to it
+    #[clap(long, default_value = "10", env)]
+    db_connect_max_attempts: u32,
+    // doubles after each failed attempt, up to 30 seconds
+    #[clap(long, default_value = "500", env)]
+    db_connect_retry_delay_ms: u64,
+    // log every SQL statement with its timing at debug level
+    #[clap(long, env)]
+    db_log_queries: bool,
+    // used when DAPR_HTTP_PORT isn't set
     #[clap(default_value = "http://localhost:3500", env)]
     dapr_url: String,
+    // set by Dapr for the app it runs, and then preferred over DAPR_URL
+    #[clap(long, env)]
+    dapr_http_port: Option<u16>,
+    // this service's Dapr app id, set by Dapr alongside DAPR_HTTP_PORT
+    #[clap(long, env)]
+    app_id: Option<String>,
     #[clap(default_value = "productapi", env)]
     dapr_product_app: String,
+    // per attempt, a hung sidecar fails the publish instead of the whole request
+    #[clap(long, default_value = "2000", env)]
+    dapr_publish_timeout_ms: u64,
+    #[clap(long, default_value = "3", env)]
+    dapr_publish_max_attempts: u32,
+    #[clap(long, default_value = "100", env)]
+    dapr_publish_retry_delay_ms: u64,
+    #[clap(default_value = "5", env)]
+    http_timeout: u64,
+    #[clap(default_value = "3", env)]
+    product_max_attempts: u32,
+    #[clap(default_value = "200", env)]
+    product_retry_delay_ms: u64,
+    // product service calls allowed in flight at once, further lookups wait for a slot
+    #[clap(long, default_value = "32", env)]
+    product_max_concurrency: usize,
+    // failed product lookups in a row before calls are refused with 503
+    #[clap(long, default_value = "5", env)]
+    product_breaker_threshold: u32,
+    // seconds the circuit stays open before a probe is let through
+    #[clap(long, default_value = "30", env)]
+    product_breaker_cooldown: u64,
+    // call the product service directly instead of through the Dapr sidecar, e.g. http://localhost:5001
+    #[clap(env)]
+    product_url: Option<String>,
+    #[clap(default_value = "86400", env)]
+    idempotency_key_ttl: i64,
+    // Dapr state store caching product prices, keyed by item type
+    #[clap(long, default_value = "statestore", env)]
+    price_cache_store: String,
+    // seconds
+    #[clap(long, default_value = "300", env)]
+    price_cache_ttl: u64,
+    // skip the price cache where no Dapr state store is configured
+    #[clap(long, env)]
+    disable_price_cache: bool,
+    // skip lineItemStatusChanged events when nothing subscribes to them
+    #[clap(long, env)]
+    disable_line_item_events: bool,
+    // what /ready probes: db, product and dapr, a trailing `?` marks one optional
+    #[clap(long, default_value = "db", env)]
+    ready_checks: String,
+    // operator-only routes such as event replay, left unrouted unless set
+    #[clap(long, env)]
+    enable_admin_routes: bool,
+    // price unknown item types at 0 instead of rejecting the order
+    #[clap(long, env)]
+    lenient_pricing: bool,
+    // price from the built-in list instead of calling the product service, for local development
+    #[clap(long, env)]
+    offline_pricing: bool,
+    // what to do when the product service prices an item at zero or less
+    #[clap(long, value_enum, default_value = "reject", env)]
+    non_positive_prices: NonPositivePrices,
+    // check placed orders against the embedded JSON Schema before deserializing them
+    #[clap(long, env)]
+    validate_order_schema: bool,
+    #[clap(long, env)]
+    run_migrations: bool,
+    // seconds an unfinished order may stay open before it is cancelled; expiry is off when unset
+    #[clap(long, env)]
+    order_expiry_threshold: Option<u64>,
+    // seconds between expiry sweeps
+    #[clap(long, default_value = "60", env)]
+    order_expiry_interval: u64,
+    // seconds in-flight requests get to finish once a shutdown signal arrives before they are aborted, keep it under Nomad's kill_timeout
+    #[clap(long, default_value = "30", env)]
+    shutdown_timeout: u64,
+    // seconds before read routes answer 408
+    #[clap(long, default_value = "3", env)]
+    read_timeout: u64,
+    // seconds before write routes answer 408
+    #[clap(long, default_value = "15", env)]
+    write_timeout: u64,
+    // sustained requests a second accepted by the order write routes
+    #[clap(long, default_value = "50", env)]
+    rate_limit_per_second: u32,
+    // requests accepted at once before the sustained rate applies
+    #[clap(long, default_value = "100", env)]
+    rate_limit_burst: u32,
+    // bytes accepted in an order write request body before answering 413
+    #[clap(long, default_value = "65536", env)]
+    max_body_bytes: usize,
+    // barista and kitchen items combined
+    #[clap(long, default_value = "50", env)]
+    max_order_items: usize,
+    // per station, for what a bar or kitchen can work on from a single order
+    #[clap(long, default_value = "50", env)]
+    max_barista_items: usize,
+    #[clap(long, default_value = "50", env)]
+    max_kitchen_items: usize,
+    // read responses smaller than this many bytes are sent uncompressed
+    #[clap(long, default_value = "1024", env)]
+    compression_min_bytes: u16,
+    #[clap(long, value_enum, default_value = "pretty", env)]
+    log_format: LogFormat,
+    #[clap(long, env)]
+    otel_exporter_otlp_endpoint: Option<String>,
+    // comma-separated origins, `*` for any; CORS stays disabled when unset
+    #[clap(long, env)]
+    allowed_origins: Option<String>,
+    // ISO 4217 code every price is reported in
+    #[clap(long, default_value = "USD", env)]
+    currency: String,
+    // PEM files; HTTPS is served only when both are set
+    #[clap(long, env)]
+    tls_cert_path: Option<String>,
+    #[clap(long, env)]
+    tls_key_path: Option<String>,
+    // comma-separated URLs POSTed every order status change, signed with WEBHOOK_SECRET
+    #[clap(long, env)]
+    webhook_urls: Option<String>,
+    #[clap(long, env)]
+    webhook_secret: Option<String>,
+    // per delivery attempt
+    #[clap(long, default_value = "5000", env)]
+    webhook_timeout_ms: u64,
+    #[clap(long, default_value = "3", env)]
+    webhook_max_attempts: u32,
+    #[clap(long, default_value = "500", env)]
+    webhook_retry_delay_ms: u64,
+    // prefix every route is served under, e.g. /counter when mounted behind a gateway
+    #[clap(long, default_value = "", env)]
+    api_base_path: String,
+    // TOML or JSON file keyed by the snake_case names above, read before env vars and flags
+    #[clap(long = "config", env = "CONFIG_FILE")]
+    config_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum NonPositivePrices {
+    // fail the request with 422
+    Reject,
+    // log it and sell at that price
+    Warn,
 }
 
 #[derive(Clone)]
 struct AppState {
     config: Config,
     db_conn: DatabaseConnection,
+    dapr_client: DaprClient,
+    product_client: ProductClient,
+    metrics_handle: PrometheusHandle,
+    webhooks: WebhookNotifier,
+    order_events: OrderEvents,
+    ready_checks: Arc<[ReadyCheck]>,
+    // set only when VALIDATE_ORDER_SCHEMA is
+    order_schema: Option<OrderSchema>,
 }
 
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+const ORDER_PLACED_EVENT: &str = "orderPlaced";
+const ORDER_STATUS_CHANGED_EVENT: &str = "orderStatusChanged";
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+// SQLSTATE Postgres answers a duplicate key with
+const PG_UNIQUE_VIOLATION: &str = "23505";
+
 // Command, Query and Models
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct PlaceOrderItem {
-    item_type: Option<i32>,
+    // off-menu types fail to decode, so None means the item had none
+    item_type: Option<ItemType>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -62,7 +332,7 @@ struct SubscribeModel {
     route: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct PlaceOrder {
     command_type: Option<i32>,
@@ -74,30 +344,245 @@ struct PlaceOrder {
     timestamp: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OrderListQuery {
+    page: Option<u64>,
+    page_size: Option<u64>,
+    status: Option<String>,
+    station: Option<Station>,
+    sort: Option<OrderSort>,
+    // soft-deleted orders are left out unless this is true
+    include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OrderSearchQuery {
+    // RFC3339, inclusive
+    from: DateTime<Utc>,
+    // RFC3339, inclusive
+    to: DateTime<Utc>,
+    page: Option<u64>,
+    page_size: Option<u64>,
+    sort: Option<OrderSort>,
+    // soft-deleted orders are left out unless this is true
+    include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+enum OrderSort {
+    #[serde(rename = "createdAt")]
+    CreatedAt,
+    #[serde(rename = "-createdAt")]
+    CreatedAtDesc,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OrderQuery {
+    station: Option<Station>,
+    // a soft-deleted order answers 404 unless this is true
+    include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery {
+    // line items of soft-deleted orders are left out unless this is true
+    include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Station {
+    Barista,
+    Kitchen,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct UpdateOrderStatus {
+    status: i32,
+    // version the client last read, the update is rejected when the order has moved on
+    version: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(PagedOrderModel = PagedModel<OrderModel>)]
+#[serde(rename_all = "camelCase")]
+struct PagedModel<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub page_size: u64,
+    pub total_pages: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct OrderModel {
-    pub id: Uuid,
+    // absent on previews, which are never saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
     pub order_source: i32,
     pub loyalty_member_id: Uuid,
     pub order_status: i32,
+    pub status_name: String,
+    pub version: i32,
     pub order_lines: Vec<OrderLineModel>,
+    #[serde(serialize_with = "money::serialize")]
+    #[schema(value_type = String, example = "7.50")]
+    pub total: Decimal,
+    pub currency: String,
+    // line item status -> number of lines in it
+    pub status_counts: BTreeMap<i32, usize>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // only ever set on orders read with include_deleted=true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct VecOrderLineModel(Vec<OrderLineModel>);
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct OrderLineModel {
-    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
     pub item_type: i32,
     pub name: String,
+    #[serde(serialize_with = "money::serialize")]
+    #[schema(value_type = String, example = "3.25")]
     pub price: Decimal,
+    pub currency: String,
     pub item_status: i32,
+    pub item_status_name: String,
     pub is_barista_order: bool,
     pub order_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl OrderModel {
+    // drops the lines the given station doesn't prepare, total and statusCounts still cover the whole order
+    fn for_station(mut self, station: Option<Station>) -> Self {
+        if let Some(station) = station {
+            let is_barista_order = matches!(station, Station::Barista);
+            self.order_lines
+                .retain(|line| line.is_barista_order == is_barista_order);
+        }
+        self
+    }
+}
+
+impl From<(orders::Model, Vec<line_items::Model>)> for OrderModel {
+    fn from((order, line_items): (orders::Model, Vec<line_items::Model>)) -> Self {
+        let total = line_items.iter().map(|line_item| line_item.price).sum();
+        let mut status_counts = BTreeMap::new();
+        for line_item in &line_items {
+            *status_counts.entry(line_item.item_status).or_default() += 1;
+        }
+
+        OrderModel {
+            id: Some(order.id),
+            loyalty_member_id: order.loyalty_member_id,
+            order_source: order.order_source,
+            order_status: order.order_status,
+            status_name: status_name(order.order_status),
+            version: order.version,
+            order_lines: line_items.into_iter().map(OrderLineModel::from).collect(),
+            total,
+            currency: money::currency(),
+            status_counts,
+            created_at: order.created_at.with_timezone(&Utc),
+            updated_at: order.updated_at.with_timezone(&Utc),
+            deleted_at: order
+                .deleted_at
+                .map(|deleted_at| deleted_at.with_timezone(&Utc)),
+        }
+    }
+}
+
+impl From<line_items::Model> for OrderLineModel {
+    fn from(line_item: line_items::Model) -> Self {
+        OrderLineModel {
+            id: Some(line_item.id),
+            is_barista_order: line_item.is_barista_order,
+            item_status: line_item.item_status,
+            item_status_name: status_name(line_item.item_status),
+            item_type: line_item.item_type,
+            name: line_item.name,
+            order_id: line_item.order_id,
+            price: line_item.price,
+            currency: money::currency(),
+            created_at: line_item.created_at.with_timezone(&Utc),
+            updated_at: line_item.updated_at.with_timezone(&Utc),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderPlaced {
+    pub order_id: Uuid,
+    pub line_items: Vec<OrderLineModel>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct StationStatsModel {
+    pub barista: StationStatModel,
+    pub kitchen: StationStatModel,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct StationStatModel {
+    pub pending: i64,
+    // item type -> pending line items of that type
+    pub item_types: BTreeMap<i32, i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LineItemStatusChanged {
+    pub line_items: Vec<LineItemStatus>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LineItemStatus {
+    pub line_item_id: Uuid,
+    pub order_id: Option<Uuid>,
+    pub item_status: i32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderRepriced {
+    pub order_id: Uuid,
+    #[serde(serialize_with = "money::serialize")]
+    pub total: Decimal,
+    pub currency: String,
+    pub line_items: Vec<OrderLineModel>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderCancelled {
+    pub order_id: Uuid,
+    pub line_items: Vec<OrderLineModel>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct ReplayedEvents {
+    pub order_id: Uuid,
+    // topics republished to, in publish order
+    pub topics: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -148,40 +633,405 @@ struct KitchenOrderUp {
     pub time_up: DateTime<Utc>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct ItemTypeDto {
-    price: f32,
-    item_type: i32,
+struct Ponged {
+    pub id: Uuid,
 }
 
 #[tokio::main]
 async fn main() {
-    env::set_var("RUST_LOG", "debug");
     dotenv::dotenv().ok();
 
+    if let Some(path) = config_file_path() {
+        let known_keys = Config::command()
+            .get_arguments()
+            .map(|arg| arg.get_id().to_string())
+            .collect();
+        if let Err(reason) = load_config_file(&path, &known_keys) {
+            eprintln!("invalid config file '{}': {}", path, reason);
+            std::process::exit(1);
+        }
+    }
+
+    let mut config = Config::parse();
+    config.api_base_path = normalize_base_path(&config.api_base_path);
+    config.dapr_url = sidecar_url(config.dapr_http_port, &config.dapr_url);
+
+    if let Err(reason) = money::init_currency(&config.currency) {
+        eprintln!("invalid currency '{}': {}", config.currency, reason);
+        std::process::exit(1);
+    }
+
+    let tls_paths = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => Some((cert_path.clone(), key_path.clone())),
+        (None, None) => None,
+        _ => {
+            eprintln!("TLS_CERT_PATH and TLS_KEY_PATH must be set together");
+            std::process::exit(1);
+        }
+    };
+
+    let webhook_urls = config
+        .webhook_urls
+        .iter()
+        .flat_map(|urls| urls.split(','))
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    if !webhook_urls.is_empty() && config.webhook_secret.as_deref().unwrap_or("").is_empty() {
+        eprintln!("WEBHOOK_SECRET must be set when WEBHOOK_URLS is");
+        std::process::exit(1);
+    }
+
+    let ready_checks = match parse_ready_checks(&config.ready_checks) {
+        Ok(ready_checks) => ready_checks,
+        Err(err) => {
+            eprintln!("invalid READY_CHECKS: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let order_schema = match config
+        .validate_order_schema
+        .then(OrderSchema::compile)
+        .transpose()
+    {
+        Ok(order_schema) => order_schema,
+        Err(err) => {
+            eprintln!("invalid order schema: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let (pretty_layer, json_layer) = match config.log_format {
+        LogFormat::Pretty => (Some(tracing_subscriber::fmt::layer()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true),
+            ),
+        ),
+    };
+
+    // spans are only exported when a collector is configured, but always traced so traceparent propagates
+    let tracer = config
+        .otel_exporter_otlp_endpoint
+        .as_deref()
+        .and_then(|endpoint| {
+            init_tracer(endpoint)
+                .map_err(|err| eprintln!("OpenTelemetry exporter setup failed: {}", err))
+                .ok()
+        })
+        .unwrap_or_else(init_local_tracer);
+    let otel_layer = tracing_opentelemetry::layer().with_tracer(tracer);
+
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "counter_api=debug,tower_http=debug".into());
+    // sqlx reports statements through `log`, bridged into tracing under this target
+    let env_filter = if config.db_log_queries {
+        env_filter.add_directive("sqlx::query=debug".parse().expect("valid log directive"))
+    } else {
+        env_filter
+    };
+
     tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "counter_api=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
+        .with(env_filter)
+        .with(pretty_layer)
+        .with(json_layer)
+        .with(otel_layer)
         .init();
 
-    let config = Config::parse();
+    let mut db_options = ConnectOptions::new(config.database_url.clone());
+    db_options
+        .max_connections(config.db_max_connections)
+        .min_connections(config.db_min_connections)
+        .connect_timeout(Duration::from_secs(config.db_connect_timeout))
+        .idle_timeout(Duration::from_secs(config.db_idle_timeout))
+        .sqlx_logging(config.db_log_queries)
+        .sqlx_logging_level(log::LevelFilter::Debug);
+
+    let db_conn: DatabaseConnection = connect_with_retry(
+        db_options,
+        config.db_connect_max_attempts,
+        Duration::from_millis(config.db_connect_retry_delay_ms),
+    )
+    .await
+    .expect("Database connection failed");
 
-    let db_conn: DatabaseConnection = Database::connect(&config.database_url)
-        .await
-        .expect("Database connection failed");
+    if config.run_migrations {
+        tracing::info!("running database migrations");
+        Migrator::up(&db_conn, None)
+            .await
+            .expect("Database migration failed");
+    }
+
+    // installed before the clients below so the gauges they set at startup are kept
+    let metrics_handle = setup_metrics_recorder();
+
+    let http_client: surf::Client = surf::Config::new()
+        .set_timeout(Some(Duration::from_secs(config.http_timeout)))
+        .try_into()
+        .expect("HTTP client creation failed");
+    let dapr_client = DaprClient::new(SurfTransport(http_client.clone()), &config.dapr_url)
+        .with_publish_policy(PublishPolicy {
+            timeout: Some(Duration::from_millis(config.dapr_publish_timeout_ms)),
+            max_attempts: config.dapr_publish_max_attempts,
+            retry_delay: Duration::from_millis(config.dapr_publish_retry_delay_ms),
+        });
+
+    let product_base_url = match &config.product_url {
+        Some(product_url) => product_url.trim_end_matches('/').to_string(),
+        None => format!(
+            "{}/v1.0/invoke/{}/method",
+            config.dapr_url, config.dapr_product_app
+        ),
+    };
+    tracing::info!(
+        "running as Dapr app {} with the sidecar at {}",
+        config.app_id.as_deref().unwrap_or("(unset)"),
+        config.dapr_url
+    );
+    tracing::debug!("product_base_url: {}", product_base_url);
+    if config.offline_pricing {
+        tracing::warn!("offline pricing is active, orders are priced from the built-in list without calling the product service");
+    }
+
+    let product_client = ProductClient::new(
+        http_client.clone(),
+        &product_base_url,
+        config.product_max_attempts,
+        Duration::from_millis(config.product_retry_delay_ms),
+        config.product_max_concurrency,
+    )
+    .with_circuit_breaker(CircuitBreaker::new(
+        config.product_breaker_threshold,
+        Duration::from_secs(config.product_breaker_cooldown),
+    ));
+    let product_client = if config.disable_price_cache {
+        product_client
+    } else {
+        product_client.with_price_cache(PriceCache::new(
+            dapr_client.clone(),
+            &config.price_cache_store,
+            Duration::from_secs(config.price_cache_ttl),
+        ))
+    };
+
+    let order_events = OrderEvents::default();
+
+    let webhooks = match config.webhook_secret.as_deref() {
+        Some(secret) if !webhook_urls.is_empty() => WebhookNotifier::new(
+            http_client.clone(),
+            webhook_urls,
+            secret,
+            Duration::from_millis(config.webhook_timeout_ms),
+            config.webhook_max_attempts,
+            Duration::from_millis(config.webhook_retry_delay_ms),
+        ),
+        _ => WebhookNotifier::disabled(http_client.clone()),
+    };
+
+    let in_flight = InFlight::default();
 
     let state = AppState {
         config: config.clone(),
-        db_conn,
+        db_conn: db_conn.clone(),
+        dapr_client,
+        product_client,
+        metrics_handle,
+        webhooks,
+        order_events: order_events.clone(),
+        ready_checks: ready_checks.into(),
+        order_schema,
+    };
+
+    if let Some(threshold) = config.order_expiry_threshold {
+        let mut expiry_db_options = ConnectOptions::new(config.database_url.clone());
+        expiry_db_options
+            .max_connections(1)
+            .min_connections(0)
+            .connect_timeout(Duration::from_secs(config.db_connect_timeout))
+            .idle_timeout(Duration::from_secs(config.db_idle_timeout))
+            .sqlx_logging(config.db_log_queries)
+            .sqlx_logging_level(log::LevelFilter::Debug);
+        let expiry_db_conn = Database::connect(expiry_db_options)
+            .await
+            .expect("Order expiry database connection failed");
+
+        spawn_order_expiry(
+            AppState {
+                db_conn: expiry_db_conn,
+                ..state.clone()
+            },
+            Duration::from_secs(config.order_expiry_interval.max(1)),
+            Duration::from_secs(threshold),
+        );
+    }
+
+    let app = app_router(state, in_flight.clone());
+
+    let addr = match bind_address(&config.host, config.app_port) {
+        Ok(addr) => addr,
+        Err(reason) => {
+            eprintln!(
+                "invalid bind address '{}:{}': {}",
+                config.host, config.app_port, reason
+            );
+            std::process::exit(1);
+        }
     };
 
-    let app = Router::new()
+    let tls_config = match tls_paths {
+        Some((cert_path, key_path)) => {
+            match RustlsConfig::from_pem_file(&cert_path, &key_path).await {
+                Ok(tls_config) => Some(tls_config),
+                Err(err) => {
+                    eprintln!(
+                        "invalid TLS certificate '{}' or key '{}': {}",
+                        cert_path, key_path, err
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    tracing::debug!(
+        "listening on {}://{}",
+        if tls_config.is_some() {
+            "https"
+        } else {
+            "http"
+        },
+        addr
+    );
+
+    let shutdown = Arc::new(Notify::new());
+    let graceful = {
+        let shutdown = shutdown.clone();
+        let in_flight = in_flight.clone();
+        async move {
+            shutdown_signal(in_flight).await;
+            // open order streams would otherwise hold the server up for the whole grace period
+            order_events.close();
+            shutdown.notify_one();
+        }
+    };
+
+    let server: Pin<Box<dyn Future<Output = io::Result<()>> + Send>> = match tls_config {
+        Some(tls_config) => {
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    graceful.await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            Box::pin(
+                axum_server::bind_rustls(addr, tls_config)
+                    .handle(handle)
+                    .serve(app.into_make_service()),
+            )
+        }
+        None => Box::pin(async move {
+            axum::Server::bind(&addr)
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(graceful)
+                .await
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        }),
+    };
+
+    // returning from main drops the runtime, which aborts whatever the drain left running
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout);
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = async {
+            shutdown.notified().await;
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            tracing::error!(
+                "shutdown timed out after {:?}, forcibly aborting {} requests",
+                shutdown_timeout,
+                in_flight.count()
+            );
+        }
+    }
+
+    if let Err(err) = db_conn.close().await {
+        tracing::error!("closing database connection: {}", err);
+    }
+
+    shutdown_tracer();
+}
+
+// every route with its layers, over `state`; the server in main and the tests share it
+fn app_router(state: AppState, in_flight: InFlight) -> Router {
+    let config = state.config.clone();
+
+    let read_routes = Router::new()
         .route("/", get(home_handler))
+        .route("/health", get(health_handler))
+        .route("/version", get(version_handler))
+        .route("/ready", get(ready_handler))
+        .route("/metrics", get(metrics_handler))
         .route("/dapr/subscribe", get(get_subscribe_handler))
+        .route(
+            "/v1/api/fulfillment-orders",
+            get(get_order_handler).head(count_open_orders_handler),
+        )
+        .route("/v1/api/orders/export.csv", get(export_orders_csv_handler))
+        .route("/v1/api/orders/search", get(search_orders_handler))
+        .route("/v1/api/orders/:id", get(get_order_by_id_handler))
+        .route(
+            "/v1/api/orders/:id/line-items",
+            get(get_order_line_items_handler),
+        )
+        .route("/v1/api/stats/stations", get(get_station_stats_handler))
+        .route("/v1/api/members/:id/orders", get(get_member_orders_handler))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_timeout_error))
+                .timeout(Duration::from_secs(config.read_timeout)),
+        )
+        // gzip or br as the client's Accept-Encoding allows; order lists are where it pays off
+        .layer(
+            CompressionLayer::new().compress_when(
+                SizeAbove::new(config.compression_min_bytes)
+                    .and(NotForContentType::GRPC)
+                    .and(NotForContentType::IMAGES),
+            ),
+        );
+
+    // long-lived, so kept clear of the read timeout and of compression, which would buffer the events
+    let stream_routes = Router::new().route("/v1/api/orders/stream", get(stream_orders_handler));
+
+    // client-facing writes are rate limited and size capped; Dapr deliveries above are left alone so they aren't retried
+    let order_write_routes = Router::<AppState, Limited<Body>>::new()
+        .route("/v1/api/orders", post(place_order_handler))
+        .route("/v1/api/orders/preview", post(preview_order_handler))
+        .route("/v1/api/orders/:id", delete(delete_order_handler))
+        .route("/v1/api/orders/:id/cancel", post(cancel_order_handler))
+        .route("/v1/api/orders/:id/reprice", post(reprice_order_handler))
+        .route(
+            "/v1/api/orders/:id/status",
+            put(update_order_status_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(config.rate_limit_per_second, config.rate_limit_burst),
+            rate_limit,
+        ))
+        .layer(RequestBodyLimitLayer::new(config.max_body_bytes));
+
+    // order placement calls out to the product service, so writes get a longer budget
+    let write_routes = Router::new()
         .route(
             "/update-barista-order-line-item",
             post(update_barista_order_line_item_handler),
@@ -190,410 +1040,2001 @@ async fn main() {
             "/update-kitchen-order-line-item",
             post(update_kitchen_order_line_item_handler),
         )
-        .route("/v1/api/fulfillment-orders", get(get_order_handler))
-        .route("/v1/api/orders", post(place_order_handler))
+        .route("/ponged", post(ponged_handler))
+        .merge(order_write_routes);
+    let write_routes = if config.enable_admin_routes {
+        write_routes.route(
+            "/v1/api/orders/:id/replay-events",
+            post(replay_order_events_handler),
+        )
+    } else {
+        write_routes
+    };
+    let write_routes = write_routes.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_timeout_error))
+            .timeout(Duration::from_secs(config.write_timeout)),
+    );
+
+    let routes = Router::new()
+        .merge(read_routes)
+        .merge(stream_routes)
+        .merge(write_routes);
+    let base_path = config.api_base_path.as_str();
+    // the sidecar always asks the app root for its subscriptions, so that route is kept there too
+    let routes = if base_path.is_empty() {
+        routes
+    } else {
+        Router::new()
+            .route("/dapr/subscribe", get(get_subscribe_handler))
+            .nest(base_path, routes)
+    };
+
+    let mut api_doc = ApiDoc::openapi();
+    if !base_path.is_empty() {
+        api_doc.servers = Some(vec![Server::new(base_path)]);
+    }
+
+    let app = routes
+        .merge(
+            SwaggerUi::new(format!("{}/swagger", base_path))
+                .url(format!("{}/openapi.json", base_path), api_doc),
+        )
+        .route_layer(middleware::from_fn(track_metrics))
         .layer(
             ServiceBuilder::new()
-                .layer(HandleErrorLayer::new(|error: BoxError| async move {
-                    if error.is::<tower::timeout::error::Elapsed>() {
-                        Ok(StatusCode::REQUEST_TIMEOUT)
-                    } else {
-                        Err((
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            format!("Unhandled internal error: {}", error),
-                        ))
-                    }
-                }))
-                .timeout(Duration::from_secs(10))
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
                 .layer(
                     TraceLayer::new_for_http()
-                        .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+                        .make_span_with(make_request_span)
                         .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
                 )
+                .layer(PropagateRequestIdLayer::x_request_id())
                 .into_inner(),
         )
+        .layer(middleware::from_fn_with_state(in_flight, track_in_flight))
         .with_state(state);
 
-    let addr: String = format!("{}:{}", config.host.as_str(), config.app_port);
-
-    tracing::debug!("listening on {}", addr);
-
-    axum::Server::bind(&addr.parse().unwrap())
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
-}
-
-async fn get_order_handler(State(app): State<AppState>) -> impl IntoResponse {
-    let ord = Order::find().all(&app.db_conn).await;
-
-    match ord {
-        Ok(result) => {
-            let mut temp = vec![];
-            for order in result {
-                let mut order_model = OrderModel {
-                    id: order.id,
-                    loyalty_member_id: order.loyalty_member_id,
-                    order_source: order.order_source,
-                    order_status: order.order_status,
-                    order_lines: Vec::new(),
-                };
-
-                let line_items = order
-                    .find_related(line_items::Entity)
-                    .all(&app.db_conn)
-                    .await
-                    .unwrap_or_default();
-                for line_item in line_items {
-                    order_model.order_lines.push(OrderLineModel {
-                        id: line_item.id,
-                        is_barista_order: line_item.is_barista_order,
-                        item_status: line_item.item_status,
-                        item_type: line_item.item_type,
-                        name: line_item.name,
-                        order_id: line_item.order_id,
-                        price: line_item.price,
-                    })
-                }
+    match config.allowed_origins.as_deref() {
+        Some(allowed_origins) => app
+            .layer(cors_layer(allowed_origins))
+            .layer(middleware::from_fn(preflight_no_content)),
+        None => app,
+    }
+}
 
-                temp.push(order_model);
+async fn connect_with_retry(
+    options: ConnectOptions,
+    max_attempts: u32,
+    retry_delay: Duration,
+) -> Result<DatabaseConnection, DbErr> {
+    let max_attempts = max_attempts.max(1);
+    let mut delay = retry_delay;
+    let mut attempt = 1;
+    loop {
+        match Database::connect(options.clone()).await {
+            Ok(db_conn) => return Ok(db_conn),
+            Err(err) if attempt < max_attempts => {
+                tracing::warn!(
+                    "database connection attempt {}/{} failed: {}, retrying in {:?}",
+                    attempt,
+                    max_attempts,
+                    err,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(30));
+                attempt += 1;
+            }
+            Err(err) => {
+                tracing::error!(
+                    "database connection failed after {} attempts: {}",
+                    max_attempts,
+                    err
+                );
+                return Err(err);
             }
-
-            (StatusCode::OK, Json(temp))
-        }
-        Err(err) => {
-            // bail!("err: {}", err)
-            (StatusCode::OK, Json(vec![]))
         }
     }
 }
 
-async fn place_order_handler(
-    State(app): State<AppState>,
-    Json(input): Json<PlaceOrder>,
-) -> impl IntoResponse {
-    let txn = app.db_conn.begin().await.unwrap();
-
-    let result = orders::ActiveModel {
-        order_source: Set(input.order_source.unwrap_or(0)),
-        loyalty_member_id: Set(input.loyalty_member_id.unwrap_or_default()),
-        order_status: Set(1),
-        ..Default::default()
+// `/counter/` and `counter` both become `/counter`, blank stays blank
+fn normalize_base_path(path: &str) -> String {
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", path)
     }
-    .save(&app.db_conn)
-    .await
-    .unwrap();
+}
 
-    // barista
-    let barista_items_vec = input.barista_items.unwrap();
-    if barista_items_vec.iter().len() > 0 {
-        let params = process_params(&barista_items_vec);
-        let product_items =
-            get_product_items(&app.config.dapr_url, &app.config.dapr_product_app, params).await;
-        tracing::debug!("product_items: {:?}", product_items);
-
-        for barista_item in barista_items_vec {
-            let product_item_result = product_items
-                .clone()
-                .into_iter()
-                .find(|i| i.item_type == barista_item.item_type.unwrap_or_default());
+// accepts IPs (`0.0.0.0`, `::` or `[::]`) as well as resolvable host names like `localhost`
+fn bind_address(host: &str, port: u16) -> Result<SocketAddr, String> {
+    let host = host.trim();
+    let unbracketed = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if let Ok(ip) = unbracketed.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, port));
+    }
 
-            let price = if let Some(product_item) = product_item_result {
-                product_item.price
-            } else {
-                0.0
-            };
+    (host, port)
+        .to_socket_addrs()
+        .map_err(|err| err.to_string())?
+        .next()
+        .ok_or_else(|| "host did not resolve to any address".to_string())
+}
 
-            let order_line_result = line_items::ActiveModel {
-                item_type: Set(barista_item.item_type.unwrap_or_default()),
-                name: Set(barista_item.item_type.unwrap_or_default().to_string()),
-                price: Set(Decimal::from_f32_retain(price).unwrap_or_default()),
-                item_status: Set(0),
-                is_barista_order: Set(true),
-                order_id: result.id.clone().into(),
-                ..Default::default()
-            }
-            .save(&app.db_conn)
-            .await
-            .unwrap();
-
-            // publish domain event
-            publish_barista_order_in_event(
-                &app.config.dapr_url,
-                "baristapubsub",
-                "baristaordered",
-                BaristaOrderIn {
-                    order_id: result.id.clone().take().unwrap(),
-                    item_line_id: order_line_result.id.clone().take().unwrap(),
-                    item_type: barista_item.item_type.unwrap_or_default(),
-                    time_in: Utc::now(),
-                },
-            )
-            .await;
-        }
+async fn handle_timeout_error(error: BoxError) -> Result<StatusCode, (StatusCode, String)> {
+    if error.is::<tower::timeout::error::Elapsed>() {
+        Ok(StatusCode::REQUEST_TIMEOUT)
+    } else {
+        Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unhandled internal error: {}", error),
+        ))
     }
+}
 
-    // kitchen
-    let kitchen_items_vec = input.kitchen_items.unwrap();
-    if kitchen_items_vec.iter().len() > 0 {
-        let params = process_params(&kitchen_items_vec);
-        let product_items =
-            get_product_items(&app.config.dapr_url, &app.config.dapr_product_app, params).await;
-        tracing::debug!("product_items: {:?}", product_items);
-
-        for kitchen_item in kitchen_items_vec {
-            let product_item_result = product_items
-                .clone()
-                .into_iter()
-                .find(|i| i.item_type == kitchen_item.item_type.unwrap_or_default());
-
-            let price = if let Some(product_item) = product_item_result {
-                product_item.price
-            } else {
-                0.0
-            };
+fn make_request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = %request_id,
+    );
+    // continue the caller's trace when it sent a traceparent
+    span.set_parent(remote_context(request.headers()));
+    span
+}
 
-            let order_line_result = line_items::ActiveModel {
-                item_type: Set(kitchen_item.item_type.unwrap_or_default()),
-                name: Set(kitchen_item.item_type.unwrap_or_default().to_string()),
-                price: Set(Decimal::from_f32_retain(price).unwrap_or_default()),
-                item_status: Set(0),
-                is_barista_order: Set(false),
-                order_id: result.id.clone().into(),
-                ..Default::default()
-            }
-            .save(&app.db_conn)
-            .await
-            .unwrap();
-
-            // publish domain event
-            publish_kitchen_order_in_event(
-                &app.config.dapr_url,
-                "kitchenpubsub",
-                "kitchenordered",
-                KitchenOrderIn {
-                    order_id: result.id.clone().take().unwrap(),
-                    item_line_id: order_line_result.id.clone().take().unwrap(),
-                    item_type: kitchen_item.item_type.unwrap_or_default(),
-                    time_in: Utc::now(),
-                },
-            )
-            .await;
-        }
-    }
+#[utoipa::path(
+    get,
+    path = "/v1/api/fulfillment-orders",
+    params(OrderListQuery),
+    responses(
+        (status = 200, description = "Page of orders", body = PagedOrderModel),
+        (status = 400, description = "Invalid status, station or sort"),
+        (status = 500, description = "Database error"),
+    )
+)]
+async fn get_order_handler(
+    State(app): State<AppState>,
+    Query(query): Query<OrderListQuery>,
+) -> Result<Json<PagedModel<OrderModel>>, AppError> {
+    let orders = list_orders(&app.db_conn, None, None, query).await?;
 
-    txn.commit().await.unwrap();
+    Ok(Json(orders))
+}
 
-    result.id.unwrap().to_string()
+// lets a dashboard poll for new work with a COUNT(*) instead of fetching the list
+#[utoipa::path(
+    head,
+    path = "/v1/api/fulfillment-orders",
+    responses(
+        (status = 200, description = "No body, the number of open orders is in X-Total-Count",
+            headers(("x-total-count" = u64, description = "Orders placed or in progress"))),
+        (status = 500, description = "Database error"),
+    )
+)]
+async fn count_open_orders_handler(
+    State(app): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let open_orders = Order::find()
+        .filter(orders::Column::DeletedAt.is_null())
+        .filter(orders::Column::OrderStatus.is_in([
+            i32::from(OrderStatus::Placed),
+            i32::from(OrderStatus::InProgress),
+        ]))
+        .count(&app.db_conn)
+        .await?;
+
+    Ok([(TOTAL_COUNT_HEADER, open_orders.to_string())])
 }
 
-async fn home_handler() -> impl IntoResponse {
-    StatusCode::OK
+// Server-Sent Events, one per event published to Dapr with its topic as name and its body as data
+#[utoipa::path(
+    get,
+    path = "/v1/api/orders/stream",
+    params(("last-event-id" = Option<u64>, Header, description = "Resume after this event, replaying the recent ones still buffered")),
+    responses(
+        (status = 200, description = "Stream of order events", body = String, content_type = "text/event-stream"),
+    )
+)]
+async fn stream_orders_handler(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let last_event_id = headers
+        .get(LAST_EVENT_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    let events = app.order_events.subscribe(last_event_id).map(|event| {
+        Ok(SseEvent::default()
+            .id(event.id.to_string())
+            .event(event.name)
+            .data(event.data))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
 }
 
-async fn get_subscribe_handler() -> impl IntoResponse {
-    let subscribe_model = vec![
-        SubscribeModel {
-            pubsubname: "baristaorderuppubsub".to_string(),
-            topic: "baristaorderup".to_string(),
-            route: "update-barista-order-line-item".to_string(),
-        },
-        SubscribeModel {
-            pubsubname: "kitchenorderuppubsub".to_string(),
-            topic: "kitchenorderup".to_string(),
-            route: "update-kitchen-order-line-item".to_string(),
-        },
-    ];
+#[utoipa::path(
+    get,
+    path = "/v1/api/members/{id}/orders",
+    params(("id" = Uuid, Path, description = "Loyalty member id"), OrderListQuery),
+    responses(
+        (status = 200, description = "Page of the member's orders, newest first unless sorted otherwise", body = PagedOrderModel),
+        (status = 400, description = "Invalid status, station or sort"),
+        (status = 500, description = "Database error"),
+    )
+)]
+async fn get_member_orders_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(mut query): Query<OrderListQuery>,
+) -> Result<Json<PagedModel<OrderModel>>, AppError> {
+    query.sort.get_or_insert(OrderSort::CreatedAtDesc);
+    let orders = list_orders(&app.db_conn, Some(id), None, query).await?;
 
-    (StatusCode::OK, Json(subscribe_model))
+    Ok(Json(orders))
 }
 
-async fn update_barista_order_line_item_handler(
+#[utoipa::path(
+    get,
+    path = "/v1/api/orders/search",
+    params(OrderSearchQuery),
+    responses(
+        (status = 200, description = "Page of orders created within the range, oldest first unless sorted otherwise", body = PagedOrderModel),
+        (status = 400, description = "Missing or malformed bounds, or from after to"),
+        (status = 500, description = "Database error"),
+    )
+)]
+async fn search_orders_handler(
     State(app): State<AppState>,
-    Json(event): Json<Event>,
-) -> impl IntoResponse {
-    tracing::debug!("barista_order_up_event: {:?}", event.data());
+    Query(query): Query<OrderSearchQuery>,
+) -> Result<Json<PagedModel<OrderModel>>, AppError> {
+    if query.from > query.to {
+        return Err(AppError::BadRequest(format!(
+            "from {} is after to {}",
+            query.from.to_rfc3339(),
+            query.to.to_rfc3339()
+        )));
+    }
 
-    let event = match event.data() {
-        Some(cloudevents::Data::Json(value)) => {
-            let temp = <BaristaOrderUp as Deserialize>::deserialize(value);
-            tracing::debug!("BaristaOrderUp: {:?}", temp);
-            temp.unwrap()
-        }
-        _ => unreachable!(),
-    };
-
-    match Order::find_by_id(event.order_id).one(&app.db_conn).await {
-        Ok(result) => {
-            tracing::debug!("Order_updating: {:?}", result);
-            if let Some(order) = result {
-                let line_item_result = order
-                    .find_related(line_items::Entity)
-                    .one(&app.db_conn)
-                    .await
-                    .unwrap_or_default();
-
-                if let Some(line_item) = line_item_result {
-                    if line_item.is_barista_order {
-                        let _ = line_items::ActiveModel {
-                            id: Set(line_item.id),
-                            item_status: Set(2), // 0=PLACED; 1=IN_PROGRESS; 2=FULFILLED
-                            ..Default::default()
-                        }
-                        .save(&app.db_conn)
-                        .await
-                        .unwrap();
-                    }
-                }
+    let orders = list_orders(
+        &app.db_conn,
+        None,
+        Some((query.from, query.to)),
+        OrderListQuery {
+            page: query.page,
+            page_size: query.page_size,
+            status: None,
+            station: None,
+            sort: Some(query.sort.unwrap_or(OrderSort::CreatedAt)),
+            include_deleted: query.include_deleted,
+        },
+    )
+    .await?;
 
-                let line_item_all_result = order
-                    .find_related(line_items::Entity)
-                    .all(&app.db_conn)
-                    .await
-                    .unwrap_or_default();
+    Ok(Json(orders))
+}
 
-                let mut all_done = true; // assume all done
-                for line_item in line_item_all_result {
-                    if line_item.item_status < 2 {
-                        all_done = false;
-                    }
-                }
+async fn list_orders(
+    db_conn: &DatabaseConnection,
+    loyalty_member_id: Option<Uuid>,
+    created_between: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    query: OrderListQuery,
+) -> Result<PagedModel<OrderModel>, AppError> {
+    let page = query.page.unwrap_or(0);
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let statuses = query.status.as_deref().map(parse_statuses).transpose()?;
+
+    let filtered = exclude_deleted(Order::find(), query.include_deleted.unwrap_or(false))
+        .apply_if(loyalty_member_id, |filtered, loyalty_member_id| {
+            filtered.filter(orders::Column::LoyaltyMemberId.eq(loyalty_member_id))
+        })
+        .apply_if(created_between, |filtered, (from, to)| {
+            filtered.filter(orders::Column::CreatedAt.between(from, to))
+        })
+        .apply_if(statuses, |filtered, statuses| {
+            filtered.filter(orders::Column::OrderStatus.is_in(statuses))
+        });
+
+    let (result, total_pages) =
+        fetch_order_page(db_conn, filtered, query.sort, page, page_size).await?;
+
+    Ok(PagedModel {
+        items: result
+            .into_iter()
+            .map(|order| order.for_station(query.station))
+            .collect(),
+        page,
+        page_size,
+        total_pages,
+    })
+}
 
-                if all_done {
-                    let _ = orders::ActiveModel {
-                        id: Set(order.id),
-                        order_status: Set(2), // 0=PLACED; 1=IN_PROGRESS; 2=FULFILLED
-                        ..Default::default()
-                    }
-                    .save(&app.db_conn)
-                    .await
-                    .unwrap();
-                }
-            };
-        }
-        Err(_) => unreachable!(),
-    };
+fn parse_statuses(status: &str) -> Result<Vec<i32>, AppError> {
+    status
+        .split(',')
+        .map(|s| {
+            s.trim()
+                .parse::<i32>()
+                .map_err(|_| AppError::BadRequest(format!("invalid status value '{}'", s)))
+        })
+        .collect()
 }
 
-async fn update_kitchen_order_line_item_handler(
-    State(app): State<AppState>,
-    Json(event): Json<Event>,
-) -> impl IntoResponse {
-    tracing::debug!("kitchen_order_up_event: {:?}", event.data());
+// `query` carries the filters; the page is sorted, then loaded with its line items
+async fn fetch_order_page(
+    db_conn: &DatabaseConnection,
+    query: Select<Order>,
+    sort: Option<OrderSort>,
+    page: u64,
+    page_size: u64,
+) -> Result<(Vec<OrderModel>, u64), DbErr> {
+    // id breaks ties so pages stay stable between requests
+    let query = match sort {
+        Some(OrderSort::CreatedAt) => query.order_by_asc(orders::Column::CreatedAt),
+        Some(OrderSort::CreatedAtDesc) => query.order_by_desc(orders::Column::CreatedAt),
+        None => query,
+    };
+    let paginator = query
+        .order_by_asc(orders::Column::Id)
+        .paginate(db_conn, page_size);
+    let total_pages = paginator.num_pages().await?;
 
-    let event = match event.data() {
-        Some(cloudevents::Data::Json(value)) => {
-            let temp = <KitchenOrderUp as Deserialize>::deserialize(value);
-            tracing::debug!("KitchenOrderUp: {:?}", temp);
-            temp.unwrap()
+    let orders = paginator.fetch_page(page).await?;
+    let line_items = orders.load_many(line_items::Entity, db_conn).await?;
+
+    let result = orders
+        .into_iter()
+        .zip(line_items)
+        .map(OrderModel::from)
+        .collect();
+
+    Ok((result, total_pages))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/api/orders/{id}",
+    params(("id" = Uuid, Path, description = "Order id"), OrderQuery),
+    responses(
+        (status = 200, description = "Order found", body = OrderModel),
+        (status = 400, description = "Invalid station"),
+        (status = 404, description = "Order not found"),
+    )
+)]
+async fn get_order_by_id_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<OrderQuery>,
+) -> Result<Json<OrderModel>, AppError> {
+    let order = find_order_model(&app.db_conn, id, query.include_deleted.unwrap_or(false)).await?;
+
+    Ok(Json(order.for_station(query.station)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/api/orders/{id}/line-items",
+    params(("id" = Uuid, Path, description = "Order id"), OrderQuery),
+    responses(
+        (status = 200, description = "Line items of the order", body = [OrderLineModel]),
+        (status = 400, description = "Invalid station"),
+        (status = 404, description = "Order not found"),
+    )
+)]
+async fn get_order_line_items_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<OrderQuery>,
+) -> Result<Json<VecOrderLineModel>, AppError> {
+    let order = find_order_model(&app.db_conn, id, query.include_deleted.unwrap_or(false)).await?;
+
+    Ok(Json(VecOrderLineModel(
+        order.for_station(query.station).order_lines,
+    )))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/api/stats/stations",
+    responses(
+        (status = 200, description = "Pending line items per station", body = StationStatsModel),
+        (status = 500, description = "Database error"),
+    )
+)]
+async fn get_station_stats_handler(
+    State(app): State<AppState>,
+) -> Result<Json<StationStatsModel>, AppError> {
+    let rows = line_items::Entity::find()
+        .select_only()
+        .column(line_items::Column::IsBaristaOrder)
+        .column(line_items::Column::ItemType)
+        .column_as(line_items::Column::Id.count(), "pending")
+        .inner_join(Order)
+        .filter(orders::Column::DeletedAt.is_null())
+        .filter(line_items::Column::ItemStatus.lt(i32::from(OrderStatus::Fulfilled)))
+        .group_by(line_items::Column::IsBaristaOrder)
+        .group_by(line_items::Column::ItemType)
+        .into_tuple::<(bool, i32, i64)>()
+        .all(&app.db_conn)
+        .await?;
+
+    let mut stats = StationStatsModel::default();
+    for (is_barista_order, item_type, pending) in rows {
+        let station = if is_barista_order {
+            &mut stats.barista
+        } else {
+            &mut stats.kitchen
+        };
+        station.pending += pending;
+        station.item_types.insert(item_type, pending);
+    }
+
+    Ok(Json(stats))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/api/orders/{id}",
+    params(("id" = Uuid, Path, description = "Order id")),
+    responses(
+        (status = 204, description = "Order soft-deleted, it stays readable with include_deleted=true"),
+        (status = 404, description = "Order not found"),
+    )
+)]
+async fn delete_order_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let txn = app.db_conn.begin().await?;
+
+    let order = Order::find_by_id(id)
+        .filter(orders::Column::DeletedAt.is_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?;
+
+    // the order and its line items stay for audits; a retried key places a new order rather than replaying a hidden one
+    idempotency_keys::Entity::delete_many()
+        .filter(idempotency_keys::Column::OrderId.eq(id))
+        .exec(&txn)
+        .await?;
+    orders::ActiveModel {
+        id: Set(order.id),
+        deleted_at: Set(Some(Utc::now().into())),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/api/orders/{id}/status",
+    params(("id" = Uuid, Path, description = "Order id")),
+    request_body = UpdateOrderStatus,
+    responses(
+        (status = 200, description = "Order status updated", body = OrderModel),
+        (status = 404, description = "Order not found"),
+        (status = 409, description = "Version mismatch or transition not allowed"),
+        (status = 422, description = "Unknown status", body = ValidationErrors),
+    )
+)]
+async fn update_order_status_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(input): Json<UpdateOrderStatus>,
+) -> Result<Json<OrderModel>, AppError> {
+    let txn = app.db_conn.begin().await?;
+
+    let order = Order::find_by_id(id)
+        .filter(orders::Column::DeletedAt.is_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?;
+
+    if order.version != input.version {
+        return Err(AppError::Conflict(format!(
+            "order {} is at version {}, not {}",
+            id, order.version, input.version
+        )));
+    }
+
+    let Ok(to) = OrderStatus::try_from(input.status) else {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "status",
+            "unknown_status",
+            format!("unknown order status {}", input.status),
+        )]));
+    };
+    // a well-formed status the order can't move to conflicts with its state, it isn't bad input
+    if !matches!(OrderStatus::try_from(order.order_status), Ok(from) if from.can_transition_to(to))
+    {
+        return Err(AppError::Conflict(format!(
+            "cannot transition order {} from {} to {}",
+            id,
+            status_name(order.order_status),
+            to.name()
+        )));
+    }
+
+    orders::ActiveModel {
+        id: Set(order.id),
+        order_status: Set(input.status),
+        version: Set(order.version + 1),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+
+    let order = find_order_model(&txn, id, false).await?;
+
+    txn.commit().await?;
+
+    notify_order_status(&app, OrderStatusChanged::new(id, order.order_status)).await;
+
+    Ok(Json(order))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/api/orders/{id}/reprice",
+    params(("id" = Uuid, Path, description = "Order id")),
+    responses(
+        (status = 200, description = "Order repriced at current product prices", body = OrderModel),
+        (status = 404, description = "Order not found"),
+        (status = 409, description = "Order already fulfilled or cancelled"),
+        (status = 422, description = "Invalid product price"),
+        (status = 502, description = "Product service unavailable"),
+        (status = 503, description = "Product service circuit open after repeated failures"),
+    )
+)]
+async fn reprice_order_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<OrderModel>, AppError> {
+    let order = find_order_model(&app.db_conn, id, false).await?;
+    ensure_not_final(&id, order.order_status, "reprice")?;
+
+    // ask the product service before taking the row lock, so the lock isn't held across the call
+    let mut item_types = order
+        .order_lines
+        .iter()
+        .map(|line| line.item_type)
+        .collect::<Vec<_>>();
+    item_types.sort_unstable();
+    item_types.dedup();
+    let params = item_types
+        .iter()
+        .map(|item_type| item_type.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let product_items = lookup_product_items(&app, params, request_id).await?;
+    let prices = to_decimal_prices(&product_items, app.config.non_positive_prices)?;
+
+    let txn = app.db_conn.begin().await?;
+
+    let order = Order::find_by_id(id)
+        .filter(orders::Column::DeletedAt.is_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?;
+    ensure_not_final(&order.id, order.order_status, "reprice")?;
+
+    let line_items = order.find_related(line_items::Entity).all(&txn).await?;
+    for line_item in line_items {
+        // item types the product service no longer knows keep the price they were sold at
+        let Some(price) = prices.get(&line_item.item_type) else {
+            continue;
+        };
+        if *price != line_item.price {
+            line_items::ActiveModel {
+                id: Set(line_item.id),
+                price: Set(*price),
+                ..Default::default()
+            }
+            .update(&txn)
+            .await?;
         }
-        _ => unreachable!(),
-    };
-
-    match Order::find_by_id(event.order_id).one(&app.db_conn).await {
-        Ok(result) => {
-            tracing::debug!("Order_updating: {:?}", result);
-            if let Some(order) = result {
-                let line_item_result = order
-                    .find_related(line_items::Entity)
-                    .one(&app.db_conn)
-                    .await
-                    .unwrap_or_default();
-
-                if let Some(line_item) = line_item_result {
-                    // kitchen item?
-                    if !line_item.is_barista_order {
-                        let _ = line_items::ActiveModel {
-                            id: Set(line_item.id),
-                            item_status: Set(2), // 0=PLACED; 1=IN_PROGRESS; 2=FULFILLED
-                            ..Default::default()
-                        }
-                        .save(&app.db_conn)
-                        .await
-                        .unwrap();
-                    }
-                }
+    }
 
-                let line_item_all_result = order
-                    .find_related(line_items::Entity)
-                    .all(&app.db_conn)
-                    .await
-                    .unwrap_or_default();
+    orders::ActiveModel {
+        id: Set(order.id),
+        version: Set(order.version + 1),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+
+    let order = find_order_model(&txn, id, false).await?;
+
+    txn.commit().await?;
+
+    if let Err(err) = pub_order_event(
+        &app,
+        "pubsub",
+        "orderRepriced",
+        OrderRepriced {
+            order_id: id,
+            total: order.total,
+            currency: order.currency.clone(),
+            line_items: order.order_lines.to_vec(),
+        },
+    )
+    .await
+    {
+        tracing::error!("pub_order_event: {}", err);
+    }
 
-                let mut all_done = true; // assume all done
-                for line_item in line_item_all_result {
-                    if line_item.item_status < 2 {
-                        all_done = false;
-                    }
-                }
+    Ok(Json(order))
+}
 
-                if all_done {
-                    let _ = orders::ActiveModel {
-                        id: Set(order.id),
-                        order_status: Set(2), // 0=PLACED; 1=IN_PROGRESS; 2=FULFILLED
-                        ..Default::default()
-                    }
-                    .save(&app.db_conn)
-                    .await
-                    .unwrap();
-                }
+// every status change goes to the webhooks and is published, which also reaches open order streams
+async fn notify_order_status(app: &AppState, changed: OrderStatusChanged) {
+    app.webhooks.notify(changed.clone());
+    if let Err(err) = pub_order_event(app, "pubsub", ORDER_STATUS_CHANGED_EVENT, changed).await {
+        tracing::error!("pub_order_event: {}", err);
+    }
+}
+
+// soft-deleted orders drop out of reads unless the caller asked for them
+fn exclude_deleted<Q: QueryFilter>(query: Q, include_deleted: bool) -> Q {
+    if include_deleted {
+        query
+    } else {
+        query.filter(orders::Column::DeletedAt.is_null())
+    }
+}
+
+fn ensure_not_final(id: &Uuid, order_status: i32, action: &str) -> Result<(), AppError> {
+    if OrderStatus::try_from(order_status).map_or(true, OrderStatus::is_final) {
+        return Err(AppError::Conflict(format!(
+            "cannot {} order {} in status {}",
+            action, id, order_status
+        )));
+    }
+
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/api/orders/{id}/cancel",
+    params(("id" = Uuid, Path, description = "Order id")),
+    responses(
+        (status = 200, description = "Order cancelled", body = OrderModel),
+        (status = 404, description = "Order not found"),
+        (status = 409, description = "Order already fulfilled or cancelled"),
+    )
+)]
+async fn cancel_order_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<OrderModel>, AppError> {
+    Ok(Json(cancel_order(&app, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/api/orders/{id}/replay-events",
+    params(("id" = Uuid, Path, description = "Order id")),
+    responses(
+        (status = 200, description = "Events republished for the order as it stands now", body = ReplayedEvents),
+        (status = 404, description = "Order not found, or ENABLE_ADMIN_ROUTES unset"),
+        (status = 502, description = "Publishing to Dapr failed"),
+        (status = 504, description = "Publishing to Dapr timed out"),
+    )
+)]
+async fn replay_order_events_handler(
+    State(app): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReplayedEvents>, AppError> {
+    let order = find_order_model(&app.db_conn, id, false).await?;
+    let mut topics = vec![];
+
+    // built from the current rows, so subscribers see today's line items and statuses
+    pub_order_event(
+        &app,
+        "pubsub",
+        "orderPlaced",
+        OrderPlaced {
+            order_id: id,
+            line_items: order.order_lines.to_vec(),
+        },
+    )
+    .await?;
+    topics.push("orderPlaced".to_string());
+
+    let line_items = order
+        .order_lines
+        .iter()
+        .filter(|line| line.item_status != i32::from(OrderStatus::Placed))
+        .filter_map(|line| {
+            line.id.map(|line_item_id| LineItemStatus {
+                line_item_id,
+                order_id: line.order_id,
+                item_status: line.item_status,
+            })
+        })
+        .collect::<Vec<_>>();
+    if !app.config.disable_line_item_events && !line_items.is_empty() {
+        pub_order_event(
+            &app,
+            "pubsub",
+            "lineItemStatusChanged",
+            LineItemStatusChanged { line_items },
+        )
+        .await?;
+        topics.push("lineItemStatusChanged".to_string());
+    }
+
+    if order.order_status == i32::from(OrderStatus::Cancelled) {
+        pub_order_event(
+            &app,
+            "pubsub",
+            "orderCancelled",
+            OrderCancelled {
+                order_id: id,
+                line_items: order.order_lines.to_vec(),
+            },
+        )
+        .await?;
+        topics.push("orderCancelled".to_string());
+    }
+
+    tracing::info!("replayed {:?} for order {}", topics, id);
+
+    Ok(Json(ReplayedEvents {
+        order_id: id,
+        topics,
+    }))
+}
+
+// shared by the cancel endpoint and the stale order expiry
+async fn cancel_order(app: &AppState, id: Uuid) -> Result<OrderModel, AppError> {
+    let txn = app.db_conn.begin().await?;
+
+    let order = Order::find_by_id(id)
+        .filter(orders::Column::DeletedAt.is_null())
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))?;
+
+    ensure_not_final(&order.id, order.order_status, "cancel")?;
+
+    orders::ActiveModel {
+        id: Set(order.id),
+        order_status: Set(OrderStatus::Cancelled.into()),
+        version: Set(order.version + 1),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+
+    line_items::Entity::update_many()
+        .col_expr(
+            line_items::Column::ItemStatus,
+            Expr::value(i32::from(OrderStatus::Cancelled)),
+        )
+        .col_expr(
+            line_items::Column::UpdatedAt,
+            Expr::current_timestamp().into(),
+        )
+        .filter(line_items::Column::OrderId.eq(id))
+        .exec(&txn)
+        .await?;
+
+    let order = find_order_model(&txn, id, false).await?;
+
+    txn.commit().await?;
+
+    notify_order_status(app, OrderStatusChanged::new(id, order.order_status)).await;
+
+    publish_line_item_status_changed(
+        app,
+        order
+            .order_lines
+            .iter()
+            .filter_map(|line| {
+                line.id.map(|line_item_id| LineItemStatus {
+                    line_item_id,
+                    order_id: line.order_id,
+                    item_status: line.item_status,
+                })
+            })
+            .collect(),
+    )
+    .await;
+
+    if let Err(err) = pub_order_event(
+        app,
+        "pubsub",
+        "orderCancelled",
+        OrderCancelled {
+            order_id: id,
+            line_items: order.order_lines.to_vec(),
+        },
+    )
+    .await
+    {
+        tracing::error!("pub_order_event: {}", err);
+    }
+
+    Ok(order)
+}
+
+async fn find_order_model<C>(
+    db: &C,
+    id: Uuid,
+    include_deleted: bool,
+) -> Result<OrderModel, AppError>
+where
+    C: ConnectionTrait,
+{
+    exclude_deleted(Order::find_by_id(id), include_deleted)
+        .find_with_related(line_items::Entity)
+        .all(db)
+        .await?
+        .into_iter()
+        .next()
+        .map(OrderModel::from)
+        .ok_or_else(|| AppError::NotFound(format!("order {} not found", id)))
+}
+
+// the body may also be msgpack, and `Accept: application/msgpack` returns the order as msgpack
+#[utoipa::path(
+    post,
+    path = "/v1/api/orders",
+    request_body = PlaceOrder,
+    params(("idempotency-key" = Option<String>, Header, description = "Replays the original order id when reused")),
+    responses(
+        (status = 200, description = "Order first placed with the same idempotency key", body = OrderModel,
+            headers(("Location" = String, description = "URL of the placed order"))),
+        (status = 201, description = "Order placed", body = OrderModel,
+            headers(("Location" = String, description = "URL of the placed order"))),
+        (status = 413, description = "Request body too large"),
+        (status = 415, description = "Body neither application/json nor application/msgpack"),
+        (status = 422, description = "Empty order, too many items or unknown item types", body = ValidationErrors),
+        (status = 502, description = "Product service unavailable"),
+        (status = 503, description = "Product service circuit open after repeated failures"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn place_order_handler(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    SchemaValidated(input): SchemaValidated<PlaceOrder>,
+) -> Result<Response, AppError> {
+    tracing::debug!(
+        command_type = ?input.command_type,
+        location = ?input.location,
+        timestamp = ?input.timestamp,
+        "placing order"
+    );
+    validate_place_order(&input, &app.config)?;
+    let format = Format::from_accept(&headers);
+
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|value| {
+            value.to_str().map(str::to_string).map_err(|_| {
+                AppError::BadRequest(format!("invalid {} header", IDEMPOTENCY_KEY_HEADER))
+            })
+        })
+        .transpose()?;
+
+    if let Some(key) = &idempotency_key {
+        if let Some(replayed) = replay_idempotent_order(&app, key, format).await? {
+            return Ok(replayed);
+        }
+    }
+
+    let barista_items_vec = input.barista_items.unwrap_or_default();
+    let kitchen_items_vec = input.kitchen_items.unwrap_or_default();
+
+    let prices = price_items(&app, &headers, &barista_items_vec, &kitchen_items_vec).await?;
+
+    let saved = save_order(
+        &app.db_conn,
+        input.order_source.unwrap_or(0),
+        input.loyalty_member_id.unwrap_or_default(),
+        barista_items_vec,
+        kitchen_items_vec,
+        &prices,
+        idempotency_key.clone(),
+    )
+    .await;
+    let (result, order_lines, station_orders) = match (saved, &idempotency_key) {
+        // a concurrent request with the same key committed first, answer with its order instead
+        (Err(AppError::Db(err)), Some(key)) if is_unique_violation(&err) => {
+            return match replay_idempotent_order(&app, key, format).await? {
+                Some(replayed) => Ok(replayed),
+                None => Err(AppError::Db(err)),
             };
         }
-        Err(_) => unreachable!(),
+        (saved, _) => saved?,
+    };
+
+    metrics::increment_counter!(ORDERS_PLACED_METRIC);
+    metrics::counter!(ORDER_LINE_ITEMS_METRIC, order_lines.len() as u64);
+
+    publish_station_orders(&app, station_orders).await;
+
+    app.webhooks
+        .notify(OrderStatusChanged::new(result.id, result.order_status));
+
+    // only announce the order once it has been committed
+    if let Err(err) = pub_order_event(
+        &app,
+        "pubsub",
+        ORDER_PLACED_EVENT,
+        OrderPlaced {
+            order_id: result.id,
+            line_items: order_lines,
+        },
+    )
+    .await
+    {
+        tracing::error!("pub_order_event: {}", err);
+    }
+
+    let order = find_order_model(&app.db_conn, result.id, false).await?;
+
+    Ok(order_response(
+        order,
+        StatusCode::CREATED,
+        format,
+        &app.config.api_base_path,
+    ))
+}
+
+// 200 with the order first placed under `key`, None when the key is unknown or has expired
+async fn replay_idempotent_order(
+    app: &AppState,
+    key: &str,
+    format: Format,
+) -> Result<Option<Response>, AppError> {
+    let Some(order_id) =
+        find_idempotent_order(&app.db_conn, key, app.config.idempotency_key_ttl).await?
+    else {
+        return Ok(None);
     };
+
+    tracing::debug!("replaying order {} for idempotency key {}", order_id, key);
+    let order = find_order_model(&app.db_conn, order_id, false).await?;
+
+    Ok(Some(order_response(
+        order,
+        StatusCode::OK,
+        format,
+        &app.config.api_base_path,
+    )))
 }
 
-fn process_params(items_vec: &[PlaceOrderItem]) -> String {
-    let params = items_vec.iter().fold("".to_string(), |acc, x| {
-        if let Some(item_type) = x.item_type {
-            tracing::debug!("item_type: {:?}", x);
-            format!("{acc},{}", item_type)
-        } else {
-            "".to_string()
+fn is_unique_violation(err: &DbErr) -> bool {
+    match err {
+        DbErr::Exec(RuntimeErr::SqlxError(err)) | DbErr::Query(RuntimeErr::SqlxError(err)) => err
+            .as_database_error()
+            .and_then(|err| err.code())
+            .map_or(false, |code| code == PG_UNIQUE_VIOLATION),
+        _ => false,
+    }
+}
+
+const ORDERS_CSV_HEADER: &str = "order_id,order_source,loyalty_member_id,order_status,order_created_at,line_item_id,item_type,name,price,currency,item_status,is_barista_order,line_item_created_at\n";
+
+#[utoipa::path(
+    get,
+    path = "/v1/api/orders/export.csv",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "One row per line item with its parent order", body = String, content_type = "text/csv"),
+    )
+)]
+async fn export_orders_csv_handler(
+    State(app): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let include_deleted = query.include_deleted.unwrap_or(false);
+    let (mut sender, body) = Body::channel();
+
+    // rows are written as the database cursor yields them, so the export is never held in memory
+    let db = app.db_conn.clone();
+    tokio::spawn(async move {
+        if sender
+            .send_data(Bytes::from_static(ORDERS_CSV_HEADER.as_bytes()))
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let rows = exclude_deleted(
+            line_items::Entity::find().find_also_related(Order),
+            include_deleted,
+        )
+        .order_by_asc(orders::Column::CreatedAt)
+        .order_by_asc(line_items::Column::OrderId)
+        .order_by_asc(line_items::Column::CreatedAt)
+        .stream(&db)
+        .await;
+        let mut rows = match rows {
+            Ok(rows) => rows,
+            Err(err) => {
+                tracing::error!("export_orders_csv: {}", err);
+                sender.abort();
+                return;
+            }
+        };
+
+        while let Some(row) = rows.next().await {
+            let line = match row {
+                Ok((line_item, order)) => orders_csv_row(&line_item, order.as_ref()),
+                Err(err) => {
+                    tracing::error!("export_orders_csv: {}", err);
+                    sender.abort();
+                    return;
+                }
+            };
+            // the client went away
+            if sender.send_data(Bytes::from(line)).await.is_err() {
+                return;
+            }
         }
     });
 
-    params
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"orders.csv\"",
+            ),
+        ],
+        boxed(body),
+    )
+        .into_response()
 }
 
-async fn get_product_items(
-    dapr_url: &str,
-    dapr_product_app: &str,
-    params: String,
-) -> Vec<ItemTypeDto> {
-    let url = format!(
-        "{}/v1.0/invoke/{}/method/v1-get-items-by-types",
-        dapr_url, dapr_product_app,
+fn orders_csv_row(line_item: &line_items::Model, order: Option<&orders::Model>) -> String {
+    let order_fields = order.map_or_else(
+        || vec![String::new(); 4],
+        |order| {
+            vec![
+                order.order_source.to_string(),
+                order.loyalty_member_id.to_string(),
+                status_name(order.order_status),
+                order.created_at.with_timezone(&Utc).to_rfc3339(),
+            ]
+        },
     );
-    tracing::debug!("url: {}", url);
 
-    surf::get(url)
-        .body(json!({ "types": params.trim_start_matches(',')}))
-        .recv_json::<Vec<ItemTypeDto>>()
+    let mut fields = vec![line_item
+        .order_id
+        .map(|order_id| order_id.to_string())
+        .unwrap_or_default()];
+    fields.extend(order_fields);
+    fields.extend([
+        line_item.id.to_string(),
+        line_item.item_type.to_string(),
+        line_item.name.clone(),
+        money::format(line_item.price),
+        money::currency(),
+        status_name(line_item.item_status),
+        line_item.is_barista_order.to_string(),
+        line_item.created_at.with_timezone(&Utc).to_rfc3339(),
+    ]);
+
+    let mut line = fields
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+// quotes fields that would otherwise break the row, doubling any quotes inside
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/api/orders/preview",
+    request_body = PlaceOrder,
+    responses(
+        (status = 200, description = "Unsaved order with its computed total", body = OrderModel),
+        (status = 413, description = "Request body too large"),
+        (status = 422, description = "Empty order, too many items or unknown item types", body = ValidationErrors),
+        (status = 502, description = "Product service unavailable"),
+        (status = 503, description = "Product service circuit open after repeated failures"),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn preview_order_handler(
+    State(app): State<AppState>,
+    headers: HeaderMap,
+    SchemaValidated(input): SchemaValidated<PlaceOrder>,
+) -> Result<Json<OrderModel>, AppError> {
+    validate_place_order(&input, &app.config)?;
+
+    let barista_items_vec = input.barista_items.unwrap_or_default();
+    let kitchen_items_vec = input.kitchen_items.unwrap_or_default();
+
+    let prices = price_items(&app, &headers, &barista_items_vec, &kitchen_items_vec).await?;
+
+    // same shape save_order would produce, built without touching the database
+    let now = Utc::now();
+    let order_lines: Vec<OrderLineModel> = barista_items_vec
+        .iter()
+        .map(|item| (item, true))
+        .chain(kitchen_items_vec.iter().map(|item| (item, false)))
+        // items without a type are skipped, as they are when saving
+        .filter_map(|(item, is_barista_order)| Some((item.item_type?, is_barista_order)))
+        .map(|(item_type, is_barista_order)| {
+            let item_type = i32::from(item_type);
+            OrderLineModel {
+                id: None,
+                item_type,
+                name: item_type.to_string(),
+                price: prices.get(&item_type).copied().unwrap_or_default(),
+                currency: money::currency(),
+                item_status: OrderStatus::Placed.into(),
+                item_status_name: OrderStatus::Placed.name().to_string(),
+                is_barista_order,
+                order_id: None,
+                created_at: now,
+                updated_at: now,
+            }
+        })
+        .collect();
+
+    let mut status_counts = BTreeMap::new();
+    status_counts.insert(OrderStatus::Placed.into(), order_lines.len());
+
+    Ok(Json(OrderModel {
+        id: None,
+        order_source: input.order_source.unwrap_or(0),
+        loyalty_member_id: input.loyalty_member_id.unwrap_or_default(),
+        order_status: OrderStatus::InProgress.into(),
+        status_name: OrderStatus::InProgress.name().to_string(),
+        version: 0,
+        total: order_lines.iter().map(|line| line.price).sum(),
+        currency: money::currency(),
+        order_lines,
+        status_counts,
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+    }))
+}
+
+// looks up barista and kitchen prices in a single call to the product service
+async fn price_items(
+    app: &AppState,
+    headers: &HeaderMap,
+    barista_items: &[PlaceOrderItem],
+    kitchen_items: &[PlaceOrderItem],
+) -> Result<HashMap<i32, Decimal>, AppError> {
+    let params = process_params(barista_items.iter().chain(kitchen_items.iter()));
+    let request_id = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    let product_items = lookup_product_items(app, params, request_id).await?;
+    tracing::debug!("product_items: {:?}", product_items);
+
+    if !app.config.lenient_pricing {
+        let errors = unknown_item_type_errors(barista_items, kitchen_items, &product_items);
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+    }
+
+    to_decimal_prices(&product_items, app.config.non_positive_prices)
+}
+
+async fn lookup_product_items(
+    app: &AppState,
+    params: String,
+    request_id: Option<&str>,
+) -> Result<Vec<ItemTypeDto>, AppError> {
+    if app.config.offline_pricing {
+        return Ok(offline_pricing::product_items(&params));
+    }
+
+    app.product_client
+        .get_product_items(params, request_id)
         .await
-        .unwrap()
 }
 
-async fn publish_barista_order_in_event(
-    dapr_url: &str,
-    pubsub_name: &str,
-    topic: &str,
-    event: BaristaOrderIn,
-) {
-    let url = format!("{}/v1.0/publish/{}/{}", dapr_url, pubsub_name, topic);
-    tracing::debug!("url: {}", url);
+fn validate_place_order(input: &PlaceOrder, config: &Config) -> Result<(), AppError> {
+    let barista_items = input.barista_items.as_deref().unwrap_or_default();
+    let kitchen_items = input.kitchen_items.as_deref().unwrap_or_default();
+
+    // items without a type are skipped, so they count towards no limit
+    let barista_count = barista_items
+        .iter()
+        .filter_map(|item| item.item_type)
+        .count();
+    let kitchen_count = kitchen_items
+        .iter()
+        .filter_map(|item| item.item_type)
+        .count();
+    let item_count = barista_count + kitchen_count;
+    let max_items = config.max_order_items;
+    // the limits apply to barista and kitchen items together, so the error is on neither list
+    if item_count == 0 {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "items",
+            "empty_order",
+            "order must contain at least one item",
+        )]));
+    }
+    if item_count > max_items {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "items",
+            "too_many_items",
+            format!("order must contain at most {} items", max_items),
+        )]));
+    }
+
+    let errors = [
+        (
+            "baristaItems",
+            "barista",
+            barista_count,
+            config.max_barista_items,
+        ),
+        (
+            "kitchenItems",
+            "kitchen",
+            kitchen_count,
+            config.max_kitchen_items,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, _, count, max)| count > max)
+    .map(|(field, station, count, max)| {
+        FieldError::new(
+            field,
+            "station_limit_exceeded",
+            format!(
+                "{} items exceed the limit of {} by {}",
+                station,
+                max,
+                count - max
+            ),
+        )
+    })
+    .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    Ok(())
+}
+
+fn order_response(
+    order: OrderModel,
+    status: StatusCode,
+    format: Format,
+    base_path: &str,
+) -> Response {
+    let location = format!(
+        "{}/v1/api/orders/{}",
+        base_path,
+        order.id.unwrap_or_default()
+    );
+
+    (
+        [(header::LOCATION, location)],
+        format.respond(status, &order),
+    )
+        .into_response()
+}
 
-    surf::post(url).body(json!(event)).await.unwrap();
+// Barista and kitchen work for a saved order, to publish once its transaction has committed
+struct StationOrders {
+    barista: Vec<BaristaOrderIn>,
+    kitchen: Vec<KitchenOrderIn>,
 }
 
-async fn publish_kitchen_order_in_event(
-    dapr_url: &str,
+// takes no Dapr client, so publishing and its retries can never hold the transaction open
+#[tracing::instrument(name = "db_transaction", skip_all)]
+async fn save_order(
+    db_conn: &DatabaseConnection,
+    order_source: i32,
+    loyalty_member_id: Uuid,
+    barista_items_vec: Vec<PlaceOrderItem>,
+    kitchen_items_vec: Vec<PlaceOrderItem>,
+    prices: &HashMap<i32, Decimal>,
+    idempotency_key: Option<String>,
+) -> Result<(orders::Model, Vec<OrderLineModel>, StationOrders), AppError> {
+    let txn = db_conn.begin().await?;
+
+    let result = orders::ActiveModel {
+        order_source: Set(order_source),
+        loyalty_member_id: Set(loyalty_member_id),
+        order_status: Set(OrderStatus::InProgress.into()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+
+    let mut order_lines = vec![];
+    let mut station_orders = StationOrders {
+        barista: vec![],
+        kitchen: vec![],
+    };
+
+    // barista
+    for item_type in barista_items_vec.iter().filter_map(|item| item.item_type) {
+        let item_type = i32::from(item_type);
+        // only lenient pricing lets an unknown item type through, at 0
+        let price = prices.get(&item_type).copied().unwrap_or_default();
+
+        let order_line_result = line_items::ActiveModel {
+            item_type: Set(item_type),
+            name: Set(item_type.to_string()),
+            price: Set(price),
+            item_status: Set(OrderStatus::Placed.into()),
+            is_barista_order: Set(true),
+            order_id: Set(Some(result.id)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        station_orders.barista.push(BaristaOrderIn {
+            order_id: result.id,
+            item_line_id: order_line_result.id,
+            item_type,
+            time_in: Utc::now(),
+        });
+
+        order_lines.push(OrderLineModel::from(order_line_result));
+    }
+
+    // kitchen
+    for item_type in kitchen_items_vec.iter().filter_map(|item| item.item_type) {
+        let item_type = i32::from(item_type);
+        // only lenient pricing lets an unknown item type through, at 0
+        let price = prices.get(&item_type).copied().unwrap_or_default();
+
+        let order_line_result = line_items::ActiveModel {
+            item_type: Set(item_type),
+            name: Set(item_type.to_string()),
+            price: Set(price),
+            item_status: Set(OrderStatus::Placed.into()),
+            is_barista_order: Set(false),
+            order_id: Set(Some(result.id)),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        station_orders.kitchen.push(KitchenOrderIn {
+            order_id: result.id,
+            item_line_id: order_line_result.id,
+            item_type,
+            time_in: Utc::now(),
+        });
+
+        order_lines.push(OrderLineModel::from(order_line_result));
+    }
+
+    if let Some(key) = idempotency_key {
+        idempotency_keys::ActiveModel {
+            key: Set(key),
+            order_id: Set(result.id),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    Ok((result, order_lines, station_orders))
+}
+
+// the order stands either way; a failed publish is logged rather than failing the placement
+async fn publish_station_orders(app: &AppState, station_orders: StationOrders) {
+    for barista_order in station_orders.barista {
+        if let Err(err) =
+            pub_order_event(app, "baristapubsub", "baristaordered", barista_order).await
+        {
+            tracing::error!("pub_order_event: {}", err);
+        }
+    }
+    for kitchen_order in station_orders.kitchen {
+        if let Err(err) =
+            pub_order_event(app, "kitchenpubsub", "kitchenordered", kitchen_order).await
+        {
+            tracing::error!("pub_order_event: {}", err);
+        }
+    }
+}
+
+// Returns the order previously placed with this key, discarding the key once its TTL has elapsed
+async fn find_idempotent_order(
+    db_conn: &DatabaseConnection,
+    key: &str,
+    ttl_seconds: i64,
+) -> Result<Option<Uuid>, DbErr> {
+    let Some(entry) = idempotency_keys::Entity::find_by_id(key.to_string())
+        .one(db_conn)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    if entry.created < Utc::now() - chrono::Duration::seconds(ttl_seconds) {
+        entry.delete(db_conn).await?;
+        return Ok(None);
+    }
+
+    Ok(Some(entry.order_id))
+}
+
+async fn home_handler() -> impl IntoResponse {
+    StatusCode::OK
+}
+
+async fn health_handler() -> impl IntoResponse {
+    (StatusCode::OK, Json(json!({ "status": "ok" })))
+}
+
+// GIT_HASH and BUILD_TIMESTAMP come from build.rs
+async fn version_handler(State(app): State<AppState>) -> impl IntoResponse {
+    let built_at = env!("BUILD_TIMESTAMP")
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "gitHash": option_env!("GIT_HASH").unwrap_or("unknown"),
+        "builtAt": built_at,
+        "appId": app.config.app_id,
+    }))
+}
+
+// 503 when a required dependency is down, 200 with status "degraded" when only optional ones are
+async fn ready_handler(State(app): State<AppState>) -> impl IntoResponse {
+    let results =
+        futures::future::join_all(app.ready_checks.iter().map(|check| probe(&app, *check))).await;
+
+    let mut body = serde_json::Map::new();
+    for result in &results {
+        let name = result.check.dependency.name();
+        let state = match (&result.error, result.check.optional) {
+            (None, _) => "ok",
+            (Some(_), true) => "degraded",
+            (Some(_), false) => "down",
+        };
+        body.insert(name.to_string(), json!(state));
+        body.insert(format!("{}LatencyMs", name), json!(result.latency_ms));
+    }
+
+    let (status_code, status) = if results
+        .iter()
+        .any(|result| result.error.is_some() && !result.check.optional)
+    {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    } else if results.iter().any(|result| result.error.is_some()) {
+        (StatusCode::OK, "degraded")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+    body.insert("status".to_string(), json!(status));
+
+    (status_code, Json(body))
+}
+
+async fn metrics_handler(State(app): State<AppState>) -> impl IntoResponse {
+    app.metrics_handle.render()
+}
+
+async fn get_subscribe_handler(State(app): State<AppState>) -> impl IntoResponse {
+    // deliveries go to the prefixed routes, Dapr posts them as given
+    let route = |path: &str| match app.config.api_base_path.as_str() {
+        "" => path.to_string(),
+        base_path => format!("{}/{}", base_path, path),
+    };
+    let subscribe_model = vec![
+        SubscribeModel {
+            pubsubname: "baristaorderuppubsub".to_string(),
+            topic: "baristaorderup".to_string(),
+            route: route("update-barista-order-line-item"),
+        },
+        SubscribeModel {
+            pubsubname: "kitchenorderuppubsub".to_string(),
+            topic: "kitchenorderup".to_string(),
+            route: route("update-kitchen-order-line-item"),
+        },
+        SubscribeModel {
+            pubsubname: "pubsub".to_string(),
+            topic: "ponged".to_string(),
+            route: route("ponged"),
+        },
+    ];
+
+    (StatusCode::OK, Json(subscribe_model))
+}
+
+// Dapr acknowledges on SUCCESS/DROP, and redelivers when the handler fails with a 5xx
+async fn ponged_handler(
+    State(app): State<AppState>,
+    Json(event): Json<Event>,
+) -> Result<impl IntoResponse, AppError> {
+    tracing::debug!("ponged_event: {:?}", event.data());
+
+    let ponged = match event.data() {
+        Some(cloudevents::Data::Json(value)) => <Ponged as Deserialize>::deserialize(value).ok(),
+        _ => None,
+    };
+
+    let Some(ponged) = ponged else {
+        tracing::warn!("dropping malformed ponged event {}", event.id());
+        return Ok(Json(json!({ "status": "DROP" })));
+    };
+
+    let Some(line_item) = line_items::Entity::find_by_id(ponged.id)
+        .one(&app.db_conn)
+        .await?
+    else {
+        tracing::warn!("dropping ponged event for unknown line item {}", ponged.id);
+        return Ok(Json(json!({ "status": "DROP" })));
+    };
+    if line_item.item_status == i32::from(OrderStatus::Cancelled) {
+        tracing::warn!(
+            "dropping ponged event for cancelled line item {}",
+            ponged.id
+        );
+        return Ok(Json(json!({ "status": "DROP" })));
+    }
+
+    line_items::ActiveModel {
+        id: Set(line_item.id),
+        item_status: Set(OrderStatus::Fulfilled.into()),
+        ..Default::default()
+    }
+    .update(&app.db_conn)
+    .await?;
+
+    publish_line_item_status_changed(
+        &app,
+        vec![LineItemStatus {
+            line_item_id: line_item.id,
+            order_id: line_item.order_id,
+            item_status: OrderStatus::Fulfilled.into(),
+        }],
+    )
+    .await;
+
+    Ok(Json(json!({ "status": "SUCCESS" })))
+}
+
+// like ponged deliveries, malformed events are dropped and database failures redelivered
+async fn update_barista_order_line_item_handler(
+    State(app): State<AppState>,
+    Json(event): Json<Event>,
+) -> Result<impl IntoResponse, AppError> {
+    tracing::debug!("barista_order_up_event: {:?}", event.data());
+
+    let order_up = match event.data() {
+        Some(cloudevents::Data::Json(value)) => {
+            <BaristaOrderUp as Deserialize>::deserialize(value).ok()
+        }
+        _ => None,
+    };
+
+    let Some(order_up) = order_up else {
+        tracing::warn!("dropping malformed barista order up event {}", event.id());
+        return Ok(Json(json!({ "status": "DROP" })));
+    };
+    tracing::info!(
+        "{} ({}) of order {}, line {}, made by {}: in at {}, up at {}",
+        order_up.name,
+        order_up.item_type,
+        order_up.order_id,
+        order_up.item_line_id,
+        order_up.made_by,
+        order_up.time_in,
+        order_up.time_up
+    );
+
+    Ok(Json(
+        fulfill_station_line_item(&app, order_up.order_id, order_up.item_line_id, true).await?,
+    ))
+}
+
+async fn update_kitchen_order_line_item_handler(
+    State(app): State<AppState>,
+    Json(event): Json<Event>,
+) -> Result<impl IntoResponse, AppError> {
+    tracing::debug!("kitchen_order_up_event: {:?}", event.data());
+
+    let order_up = match event.data() {
+        Some(cloudevents::Data::Json(value)) => {
+            <KitchenOrderUp as Deserialize>::deserialize(value).ok()
+        }
+        _ => None,
+    };
+
+    let Some(order_up) = order_up else {
+        tracing::warn!("dropping malformed kitchen order up event {}", event.id());
+        return Ok(Json(json!({ "status": "DROP" })));
+    };
+    tracing::info!(
+        "{} ({}) of order {}, line {}, made by {}: in at {}, up at {}",
+        order_up.name,
+        order_up.item_type,
+        order_up.order_id,
+        order_up.item_line_id,
+        order_up.made_by,
+        order_up.time_in,
+        order_up.time_up
+    );
+
+    Ok(Json(
+        fulfill_station_line_item(&app, order_up.order_id, order_up.item_line_id, false).await?,
+    ))
+}
+
+// marks the order's line item `item_line_id` fulfilled, and the order once every line is
+async fn fulfill_station_line_item(
+    app: &AppState,
+    order_id: Uuid,
+    item_line_id: Uuid,
+    is_barista_order: bool,
+) -> Result<serde_json::Value, AppError> {
+    let txn = app.db_conn.begin().await?;
+
+    let Some(order) = Order::find_by_id(order_id)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+    else {
+        tracing::warn!("dropping order up event for unknown order {}", order_id);
+        return Ok(json!({ "status": "DROP" }));
+    };
+    tracing::debug!("Order_updating: {:?}", order);
+
+    // an event naming a line of another order or station would never apply, so it isn't redelivered
+    let Some(line_item) = order
+        .find_related(line_items::Entity)
+        .filter(line_items::Column::Id.eq(item_line_id))
+        .filter(line_items::Column::IsBaristaOrder.eq(is_barista_order))
+        .one(&txn)
+        .await?
+    else {
+        tracing::warn!(
+            "dropping order up event for unknown line {} of order {}",
+            item_line_id,
+            order_id
+        );
+        return Ok(json!({ "status": "DROP" }));
+    };
+
+    // made after all, but a cancelled order stays cancelled
+    let cancelled = i32::from(OrderStatus::Cancelled);
+    if order.order_status == cancelled || line_item.item_status == cancelled {
+        tracing::warn!(
+            "dropping order up event for line {} of cancelled order {}",
+            item_line_id,
+            order_id
+        );
+        return Ok(json!({ "status": "DROP" }));
+    }
+
+    line_items::ActiveModel {
+        id: Set(line_item.id),
+        item_status: Set(OrderStatus::Fulfilled.into()),
+        ..Default::default()
+    }
+    .save(&txn)
+    .await?;
+
+    let all_done = order
+        .find_related(line_items::Entity)
+        .all(&txn)
+        .await?
+        .iter()
+        .all(|line_item| line_item.item_status == i32::from(OrderStatus::Fulfilled));
+    let order_fulfilled = all_done && order.order_status != i32::from(OrderStatus::Fulfilled);
+
+    if order_fulfilled {
+        orders::ActiveModel {
+            id: Set(order.id),
+            order_status: Set(OrderStatus::Fulfilled.into()),
+            version: Set(order.version + 1),
+            ..Default::default()
+        }
+        .save(&txn)
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    publish_line_item_status_changed(
+        app,
+        vec![LineItemStatus {
+            line_item_id: line_item.id,
+            order_id: line_item.order_id,
+            item_status: OrderStatus::Fulfilled.into(),
+        }],
+    )
+    .await;
+
+    if order_fulfilled {
+        notify_order_status(
+            app,
+            OrderStatusChanged::new(order.id, OrderStatus::Fulfilled.into()),
+        )
+        .await;
+    }
+
+    Ok(json!({ "status": "SUCCESS" }))
+}
+
+fn to_decimal_prices(
+    product_items: &[ItemTypeDto],
+    non_positive_prices: NonPositivePrices,
+) -> Result<HashMap<i32, Decimal>, AppError> {
+    product_items
+        .iter()
+        .map(|product_item| {
+            let price = Decimal::from_f32_retain(product_item.price).ok_or_else(|| {
+                tracing::error!(
+                    "product service priced item type {} at {}, which is not a valid decimal",
+                    product_item.item_type,
+                    product_item.price
+                );
+                AppError::UnprocessableEntity(format!(
+                    "invalid price for item type {}",
+                    product_item.item_type
+                ))
+            })?;
+            // a misconfigured product service would otherwise hand out free or negative-priced items
+            if price <= Decimal::ZERO {
+                match non_positive_prices {
+                    NonPositivePrices::Reject => {
+                        tracing::error!(
+                            "product service priced item type {} at {}, rejecting it",
+                            product_item.item_type,
+                            price
+                        );
+                        return Err(AppError::UnprocessableEntity(format!(
+                            "invalid price for item type {}",
+                            product_item.item_type
+                        )));
+                    }
+                    NonPositivePrices::Warn => tracing::warn!(
+                        "product service priced item type {} at {}, selling it at that price",
+                        product_item.item_type,
+                        price
+                    ),
+                }
+            }
+            Ok((product_item.item_type.into(), price))
+        })
+        .collect()
+}
+
+// one error per offending line, pointing at the itemType it was placed with
+fn unknown_item_type_errors(
+    barista_items: &[PlaceOrderItem],
+    kitchen_items: &[PlaceOrderItem],
+    product_items: &[ItemTypeDto],
+) -> Vec<FieldError> {
+    item_type_fields(barista_items, kitchen_items)
+        .filter_map(|(field, item)| {
+            let item_type = item.item_type?;
+            (!product_items.iter().any(|i| i.item_type == item_type)).then(|| {
+                FieldError::new(
+                    field,
+                    "unknown_item_type",
+                    format!("unknown item type {}", item_type),
+                )
+            })
+        })
+        .collect()
+}
+
+// each item paired with the `baristaItems[0].itemType` style field it answers to
+fn item_type_fields<'a>(
+    barista_items: &'a [PlaceOrderItem],
+    kitchen_items: &'a [PlaceOrderItem],
+) -> impl Iterator<Item = (String, &'a PlaceOrderItem)> {
+    let barista_items = barista_items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (format!("baristaItems[{}].itemType", index), item));
+    let kitchen_items = kitchen_items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| (format!("kitchenItems[{}].itemType", index), item));
+
+    barista_items.chain(kitchen_items)
+}
+
+// each item type once, in first-seen order; line items are priced by type so repeats add nothing
+fn process_params<'a>(items: impl IntoIterator<Item = &'a PlaceOrderItem>) -> String {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter_map(|item| item.item_type)
+        .filter(|item_type| seen.insert(*item_type))
+        .map(|item_type| item_type.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// one event per transaction, however many line items it touched
+async fn publish_line_item_status_changed(app: &AppState, line_items: Vec<LineItemStatus>) {
+    if app.config.disable_line_item_events || line_items.is_empty() {
+        return;
+    }
+
+    if let Err(err) = pub_order_event(
+        app,
+        "pubsub",
+        "lineItemStatusChanged",
+        LineItemStatusChanged { line_items },
+    )
+    .await
+    {
+        tracing::error!("pub_order_event: {}", err);
+    }
+}
+
+// whatever is published also goes out on open order streams, named after its topic, even if Dapr refuses it
+async fn pub_order_event<T: Serialize>(
+    app: &AppState,
     pubsub_name: &str,
-    topic: &str,
-    event: KitchenOrderIn,
-) {
-    let url = format!("{}/v1.0/publish/{}/{}", dapr_url, pubsub_name, topic);
-    tracing::debug!("url: {}", url);
+    topic: &'static str,
+    event: T,
+) -> Result<(), DaprError> {
+    app.order_events.send(topic, &event);
+    app.dapr_client.publish(pubsub_name, topic, &event).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(item_types: &[Option<i32>]) -> Vec<PlaceOrderItem> {
+        item_types
+            .iter()
+            .map(|item_type| PlaceOrderItem {
+                item_type: item_type.map(|item_type| ItemType::try_from(item_type).unwrap()),
+            })
+            .collect()
+    }
+
+    fn place_order(barista_items: &[Option<i32>], kitchen_items: &[Option<i32>]) -> PlaceOrder {
+        PlaceOrder {
+            command_type: None,
+            order_source: None,
+            location: None,
+            loyalty_member_id: None,
+            barista_items: Some(items(barista_items)),
+            kitchen_items: Some(items(kitchen_items)),
+            timestamp: None,
+        }
+    }
 
-    surf::post(url).body(json!(event)).await.unwrap();
+    fn error_codes(result: Result<(), AppError>) -> Vec<(String, String)> {
+        match result {
+            Err(AppError::Validation(errors)) => errors
+                .into_iter()
+                .map(|error| (error.field, error.code))
+                .collect(),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_place_order_rejects_an_empty_order() {
+        let config = Config::parse_from(["counter_api"]);
+
+        assert_eq!(
+            error_codes(validate_place_order(&place_order(&[], &[]), &config)),
+            vec![("items".to_string(), "empty_order".to_string())]
+        );
+    }
+
+    #[test]
+    fn validate_place_order_rejects_an_order_of_untyped_items() {
+        let config = Config::parse_from(["counter_api"]);
+
+        assert_eq!(
+            error_codes(validate_place_order(
+                &place_order(&[None], &[None]),
+                &config
+            )),
+            vec![("items".to_string(), "empty_order".to_string())]
+        );
+    }
+
+    #[test]
+    fn validate_place_order_accepts_a_single_item() {
+        let config = Config::parse_from(["counter_api"]);
+
+        assert!(validate_place_order(&place_order(&[Some(0)], &[]), &config).is_ok());
+        assert!(validate_place_order(&place_order(&[], &[Some(7)]), &config).is_ok());
+    }
+
+    #[test]
+    fn to_decimal_prices_rejects_prices_that_are_not_decimals() {
+        for price in [f32::NAN, f32::INFINITY, 1e30] {
+            let product_items = [ItemTypeDto {
+                price,
+                item_type: ItemType::try_from(0).unwrap(),
+            }];
+
+            assert!(
+                matches!(
+                    to_decimal_prices(&product_items, NonPositivePrices::Warn),
+                    Err(AppError::UnprocessableEntity(_))
+                ),
+                "{}",
+                price
+            );
+        }
+    }
+
+    #[test]
+    fn process_params_is_empty_without_items() {
+        assert_eq!(process_params(&items(&[])), "");
+    }
+
+    #[test]
+    fn process_params_skips_items_without_a_type() {
+        assert_eq!(process_params(&items(&[None, None])), "");
+    }
+
+    #[test]
+    fn process_params_joins_the_typed_items() {
+        assert_eq!(
+            process_params(&items(&[Some(3), None, Some(0), None, Some(7)])),
+            "3,0,7"
+        );
+    }
 }
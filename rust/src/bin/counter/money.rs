@@ -0,0 +1,33 @@
+use once_cell::sync::OnceCell;
+use rust_decimal::RoundingStrategy;
+use sea_orm::prelude::Decimal;
+use serde::Serializer;
+
+static CURRENCY: OnceCell<String> = OnceCell::new();
+
+// set once at startup from CURRENCY, before any order is served
+pub fn init_currency(code: &str) -> Result<(), String> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err("expected a three-letter ISO 4217 code such as USD".to_string());
+    }
+
+    CURRENCY
+        .set(code)
+        .map_err(|_| "currency already initialised".to_string())
+}
+
+pub fn currency() -> String {
+    CURRENCY.get().map_or("USD", String::as_str).to_string()
+}
+
+// always two fractional digits, rounding half away from zero
+pub fn format(value: Decimal) -> String {
+    let mut value = value.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    value.rescale(2);
+    value.to_string()
+}
+
+pub fn serialize<S: Serializer>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(*value))
+}
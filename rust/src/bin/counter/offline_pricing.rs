@@ -0,0 +1,33 @@
+use crate::{item_type::ItemType, product::ItemTypeDto};
+
+// The product service's own price list, used in its place when OFFLINE_PRICING is set
+const OFFLINE_PRICES: &[(i32, f32)] = &[
+    (0, 4.5),  // CAPPUCCINO
+    (1, 3.0),  // COFFEE_BLACK
+    (2, 3.0),  // COFFEE_WITH_ROOM
+    (3, 3.5),  // ESPRESSO
+    (4, 4.5),  // ESPRESSO_DOUBLE
+    (5, 4.5),  // LATTE
+    (6, 2.5),  // CAKEPOP
+    (7, 3.25), // CROISSANT
+    (8, 3.0),  // MUFFIN
+    (9, 3.5),  // CROISSANT_CHOCOLATE
+];
+
+// answers like the product service would: one entry per known item type in `params`
+pub fn product_items(params: &str) -> Vec<ItemTypeDto> {
+    params
+        .split(',')
+        .filter_map(|item_type| item_type.parse::<i32>().ok())
+        .filter_map(|item_type| ItemType::try_from(item_type).ok())
+        .filter_map(|item_type| {
+            OFFLINE_PRICES
+                .iter()
+                .find(|(known, _)| *known == i32::from(item_type))
+                .map(|(_, price)| ItemTypeDto {
+                    price: *price,
+                    item_type,
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,47 @@
+use utoipa::OpenApi;
+
+use crate::{
+    error::{FieldError, ValidationErrors},
+    item_type::ItemType,
+    OrderLineModel, OrderModel, OrderSort, PagedOrderModel, PlaceOrder, PlaceOrderItem,
+    ReplayedEvents, Station, StationStatModel, StationStatsModel, UpdateOrderStatus,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::get_order_handler,
+        crate::count_open_orders_handler,
+        crate::stream_orders_handler,
+        crate::get_order_by_id_handler,
+        crate::get_order_line_items_handler,
+        crate::export_orders_csv_handler,
+        crate::place_order_handler,
+        crate::preview_order_handler,
+        crate::update_order_status_handler,
+        crate::delete_order_handler,
+        crate::cancel_order_handler,
+        crate::reprice_order_handler,
+        crate::replay_order_events_handler,
+        crate::get_station_stats_handler,
+        crate::get_member_orders_handler,
+        crate::search_orders_handler,
+    ),
+    components(schemas(
+        PlaceOrder,
+        PlaceOrderItem,
+        ItemType,
+        UpdateOrderStatus,
+        OrderModel,
+        OrderLineModel,
+        PagedOrderModel,
+        StationStatsModel,
+        StationStatModel,
+        Station,
+        OrderSort,
+        ValidationErrors,
+        FieldError,
+        ReplayedEvents,
+    ))
+)]
+pub struct ApiDoc;
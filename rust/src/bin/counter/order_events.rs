@@ -0,0 +1,107 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use futures::{stream, Stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// events kept for clients resuming with Last-Event-ID, and how far a stream may fall behind
+const REPLAY_BUFFER: usize = 256;
+
+// One server-sent event; ids count up from 1 and start over when the process restarts
+#[derive(Debug, Clone)]
+pub struct OrderEvent {
+    pub id: u64,
+    pub name: &'static str,
+    pub data: String,
+}
+
+// Fans order placements and status changes out to every open /v1/api/orders/stream connection
+#[derive(Clone)]
+pub struct OrderEvents(Arc<Mutex<Inner>>);
+
+struct Inner {
+    // taken on shutdown, which ends every open stream
+    sender: Option<broadcast::Sender<OrderEvent>>,
+    recent: VecDeque<OrderEvent>,
+    last_id: u64,
+}
+
+impl Default for OrderEvents {
+    fn default() -> Self {
+        OrderEvents(Arc::new(Mutex::new(Inner {
+            sender: Some(broadcast::channel(REPLAY_BUFFER).0),
+            recent: VecDeque::with_capacity(REPLAY_BUFFER),
+            last_id: 0,
+        })))
+    }
+}
+
+impl OrderEvents {
+    pub fn send<T: Serialize>(&self, name: &'static str, payload: &T) {
+        let data = match serde_json::to_string(payload) {
+            Ok(data) => data,
+            Err(err) => {
+                tracing::error!("encoding {} event: {}", name, err);
+                return;
+            }
+        };
+
+        let mut inner = self.0.lock().unwrap();
+        if inner.sender.is_none() {
+            return;
+        }
+        inner.last_id += 1;
+        let event = OrderEvent {
+            id: inner.last_id,
+            name,
+            data,
+        };
+        if inner.recent.len() == REPLAY_BUFFER {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(event.clone());
+        if let Some(sender) = &inner.sender {
+            // no open streams is fine, the event is still buffered for resumes
+            let _ = sender.send(event);
+        }
+    }
+
+    // buffered events after `last_event_id`, then live ones; the lock keeps the two from overlapping or leaving a gap.
+    // a client too slow to keep up is disconnected, and resumes from the buffer when it reconnects
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> impl Stream<Item = OrderEvent> {
+        let inner = self.0.lock().unwrap();
+        let receiver = inner.sender.as_ref().map(broadcast::Sender::subscribe);
+        let missed = last_event_id
+            .map(|last_event_id| {
+                inner
+                    .recent
+                    .iter()
+                    .filter(|event| event.id > last_event_id)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        drop(inner);
+
+        let live = stream::unfold(receiver, |receiver| async move {
+            let mut receiver = receiver?;
+            match receiver.recv().await {
+                Ok(event) => Some((event, Some(receiver))),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("order stream fell {} events behind, closing it", skipped);
+                    None
+                }
+                Err(RecvError::Closed) => None,
+            }
+        });
+
+        stream::iter(missed).chain(live)
+    }
+
+    pub fn close(&self) {
+        self.0.lock().unwrap().sender = None;
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::FromRequest,
+    http::Request,
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use jsonschema::{error::ValidationErrorKind, paths::PathChunk, JSONSchema};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    content::{decode_error, Negotiated},
+    error::{AppError, FieldError},
+    item_type::MAX_ITEM_TYPE,
+    AppState,
+};
+
+const PLACE_ORDER_SCHEMA: &str = include_str!("place_order.schema.json");
+
+// The PlaceOrder JSON Schema, compiled once at startup; ranges and enums it knows about are rejected before any business logic runs
+#[derive(Clone)]
+pub struct OrderSchema(Arc<JSONSchema>);
+
+impl OrderSchema {
+    pub fn compile() -> Result<Self, String> {
+        let mut schema: Value =
+            serde_json::from_str(PLACE_ORDER_SCHEMA).map_err(|err| err.to_string())?;
+        // the menu range comes from ItemType, so the schema can't drift from what serde accepts
+        let item_type = schema
+            .pointer_mut("/definitions/item/properties/itemType")
+            .and_then(Value::as_object_mut)
+            .ok_or("order schema has no itemType property")?;
+        item_type.insert("minimum".to_string(), 0.into());
+        item_type.insert("maximum".to_string(), MAX_ITEM_TYPE.into());
+        JSONSchema::options()
+            // the draft 7 format list has no uuid, check it the way serde will parse it
+            .with_format("uuid", |value| Uuid::parse_str(value).is_ok())
+            .compile(&schema)
+            .map(|schema| OrderSchema(Arc::new(schema)))
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn validate(&self, body: &Value) -> Result<(), AppError> {
+        let errors = match self.0.validate(body) {
+            Ok(()) => return Ok(()),
+            Err(errors) => errors,
+        };
+
+        Err(AppError::Validation(
+            errors
+                .map(|error| {
+                    let keyword = error
+                        .schema_path
+                        .iter()
+                        .rev()
+                        .find_map(|chunk| match chunk {
+                            PathChunk::Keyword(keyword) => Some(*keyword),
+                            _ => None,
+                        })
+                        .unwrap_or("schema");
+                    let mut field = field_path(error.instance_path.iter());
+                    // point a missing property at itself rather than at the object lacking it
+                    if let ValidationErrorKind::Required {
+                        property: Value::String(property),
+                    } = &error.kind
+                    {
+                        if !field.is_empty() {
+                            field.push('.');
+                        }
+                        field.push_str(property);
+                    }
+                    if field.is_empty() {
+                        field = "body".to_string();
+                    }
+                    FieldError::new(field, &error_code(keyword), error.to_string())
+                })
+                .collect(),
+        ))
+    }
+}
+
+// Request body checked against the order schema when VALIDATE_ORDER_SCHEMA is set, straight serde otherwise
+pub struct SchemaValidated<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<AppState, B> for SchemaValidated<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<B>, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(schema) = &state.order_schema else {
+            let Negotiated(value) = Negotiated::<T>::from_request(req, state).await?;
+            return Ok(SchemaValidated(value));
+        };
+
+        let Negotiated(body) = Negotiated::<Value>::from_request(req, state).await?;
+        schema
+            .validate(&body)
+            .map_err(IntoResponse::into_response)?;
+        // whatever the schema lets through that serde still refuses reads like a Negotiated decode failure
+        serde_path_to_error::deserialize(body)
+            .map(SchemaValidated)
+            .map_err(|err| decode_error(err).into_response())
+    }
+}
+
+// `/baristaItems/0/itemType` becomes `baristaItems[0].itemType`, the form FieldError uses elsewhere
+fn field_path<'a>(chunks: impl Iterator<Item = &'a PathChunk>) -> String {
+    let mut field = String::new();
+    for chunk in chunks {
+        match chunk {
+            PathChunk::Index(index) => field.push_str(&format!("[{}]", index)),
+            PathChunk::Property(name) => {
+                if !field.is_empty() {
+                    field.push('.');
+                }
+                field.push_str(name);
+            }
+            // instance paths never hold schema keywords
+            PathChunk::Keyword(_) => {}
+        }
+    }
+    field
+}
+
+// `maxItems` becomes `schema_max_items`
+fn error_code(keyword: &str) -> String {
+    let mut code = String::from("schema_");
+    for c in keyword.chars() {
+        if c.is_ascii_uppercase() {
+            code.push('_');
+        }
+        code.push(c.to_ascii_lowercase());
+    }
+    code
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use dapr_client::{DaprError, StateItem};
+
+use crate::{dapr::DaprClient, item_type::ItemType, product::ItemTypeDto};
+
+// Read-through cache of product prices kept in a Dapr state store, keyed by item type
+#[derive(Clone)]
+pub struct PriceCache {
+    dapr_client: DaprClient,
+    store_name: String,
+    ttl: Duration,
+}
+
+impl PriceCache {
+    pub fn new(dapr_client: DaprClient, store_name: &str, ttl: Duration) -> Self {
+        PriceCache {
+            dapr_client,
+            store_name: store_name.to_string(),
+            ttl,
+        }
+    }
+
+    pub async fn get_many(&self, item_types: &[ItemType]) -> Result<Vec<ItemTypeDto>, DaprError> {
+        let keys = item_types
+            .iter()
+            .map(|item_type| cache_key(*item_type))
+            .collect::<Vec<_>>();
+        let items = self
+            .dapr_client
+            .get_bulk_state::<ItemTypeDto>(&self.store_name, &keys)
+            .await?;
+        tracing::debug!(
+            "price cache hits: {:?}",
+            items
+                .iter()
+                .filter(|item| item.data.is_some())
+                .map(|item| item.key.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        Ok(items.into_iter().filter_map(|item| item.data).collect())
+    }
+
+    pub async fn save_many(&self, product_items: &[ItemTypeDto]) -> Result<(), DaprError> {
+        let ttl_seconds = self.ttl.as_secs().to_string();
+        let entries = product_items
+            .iter()
+            .map(|item| {
+                StateItem::new(cache_key(item.item_type), item)
+                    .map(|entry| entry.with_metadata("ttlInSeconds", ttl_seconds.as_str()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.dapr_client
+            .save_state(&self.store_name, &entries)
+            .await
+    }
+}
+
+fn cache_key(item_type: ItemType) -> String {
+    format!("item-price-{}", item_type)
+}
@@ -0,0 +1,253 @@
+use std::{sync::Arc, time::Duration};
+
+use dapr_client::backoff_delay;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::{
+    circuit_breaker::CircuitBreaker, error::AppError, item_type::ItemType, price_cache::PriceCache,
+    telemetry,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemTypeDto {
+    pub price: f32,
+    pub item_type: ItemType,
+}
+
+// Client for the product service, retrying transient failures with exponential backoff.
+// Clones share one semaphore, capping the calls in flight to the product service at once.
+#[derive(Clone)]
+pub struct ProductClient {
+    http_client: surf::Client,
+    base_url: String,
+    url: String,
+    max_attempts: u32,
+    retry_delay: Duration,
+    price_cache: Option<PriceCache>,
+    circuit_breaker: Option<CircuitBreaker>,
+    permits: Arc<Semaphore>,
+}
+
+// transient failures are retried and count against the circuit breaker, permanent ones are neither
+enum FetchError {
+    Transient(String),
+    Permanent(String),
+}
+
+impl From<FetchError> for AppError {
+    fn from(err: FetchError) -> Self {
+        match err {
+            FetchError::Transient(err) | FetchError::Permanent(err) => {
+                AppError::ProductService(err)
+            }
+        }
+    }
+}
+
+impl ProductClient {
+    pub fn new(
+        http_client: surf::Client,
+        base_url: &str,
+        max_attempts: u32,
+        retry_delay: Duration,
+        max_concurrency: usize,
+    ) -> Self {
+        ProductClient {
+            http_client,
+            base_url: base_url.to_string(),
+            url: format!("{}/v1/api/items-by-types", base_url),
+            max_attempts: max_attempts.max(1),
+            retry_delay,
+            price_cache: None,
+            circuit_breaker: None,
+            permits: Arc::new(Semaphore::new(max_concurrency.max(1))),
+        }
+    }
+
+    pub fn with_price_cache(mut self, price_cache: PriceCache) -> Self {
+        self.price_cache = Some(price_cache);
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    // a single GET of the service root for readiness, bypassing retries and the circuit breaker
+    pub async fn probe(&self) -> Result<(), String> {
+        let res = self
+            .http_client
+            .get(format!("{}/", self.base_url))
+            .await
+            .map_err(|err| err.to_string())?;
+        if !res.status().is_success() {
+            return Err(format!("unexpected status {}", res.status()));
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn get_product_items(
+        &self,
+        params: String,
+        request_id: Option<&str>,
+    ) -> Result<Vec<ItemTypeDto>, AppError> {
+        let Some(price_cache) = &self.price_cache else {
+            return self.fetch_with_retries(&params, request_id).await;
+        };
+
+        let mut item_types = params
+            .split(',')
+            .filter_map(|item_type| item_type.parse::<i32>().ok())
+            .filter_map(|item_type| ItemType::try_from(item_type).ok())
+            .collect::<Vec<_>>();
+        item_types.sort_unstable();
+        item_types.dedup();
+
+        // a broken state store only costs us the cache, not the order
+        let mut product_items = price_cache
+            .get_many(&item_types)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("price cache lookup failed: {}", err);
+                vec![]
+            });
+
+        let misses = item_types
+            .iter()
+            .filter(|item_type| {
+                !product_items
+                    .iter()
+                    .any(|item| item.item_type == **item_type)
+            })
+            .map(|item_type| item_type.to_string())
+            .collect::<Vec<_>>();
+        if misses.is_empty() {
+            return Ok(product_items);
+        }
+
+        let fetched = self
+            .fetch_with_retries(&misses.join(","), request_id)
+            .await?;
+        if let Err(err) = price_cache.save_many(&fetched).await {
+            tracing::warn!("price cache write failed: {}", err);
+        }
+        product_items.extend(fetched);
+
+        Ok(product_items)
+    }
+
+    // fails fast while the circuit is open; a lookup that exhausts its retries counts as one failure.
+    // 4xx and undecodable answers come from a service that is up, so they never open the circuit
+    async fn fetch_with_retries(
+        &self,
+        params: &str,
+        request_id: Option<&str>,
+    ) -> Result<Vec<ItemTypeDto>, AppError> {
+        let Some(circuit_breaker) = &self.circuit_breaker else {
+            return Ok(self
+                .fetch_with_retries_unguarded(params, request_id)
+                .await?);
+        };
+
+        if let Err(retry_in) = circuit_breaker.try_acquire() {
+            return Err(AppError::ServiceUnavailable(format!(
+                "product service unavailable, retry in {}s",
+                retry_in.as_secs_f64().ceil().max(1.0) as u64
+            )));
+        }
+
+        let result = self.fetch_with_retries_unguarded(params, request_id).await;
+        match &result {
+            Err(FetchError::Transient(_)) => circuit_breaker.record_failure(),
+            _ => circuit_breaker.record_success(),
+        }
+
+        Ok(result?)
+    }
+
+    async fn fetch_with_retries_unguarded(
+        &self,
+        params: &str,
+        request_id: Option<&str>,
+    ) -> Result<Vec<ItemTypeDto>, FetchError> {
+        tracing::debug!("url: {}", self.url);
+
+        // every attempt belongs to the same trace, even when this one had to be started here
+        let traceparent = telemetry::traceparent();
+        let mut attempt = 1;
+        loop {
+            match self
+                .fetch_product_items(params, request_id, &traceparent)
+                .await
+            {
+                Ok(items) => return Ok(items),
+                Err(FetchError::Transient(err)) if attempt < self.max_attempts => {
+                    let delay = backoff_delay(self.retry_delay, attempt);
+                    tracing::warn!(
+                        "product lookup attempt {} failed: {}, retrying in {:?}",
+                        attempt,
+                        err,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn fetch_product_items(
+        &self,
+        params: &str,
+        request_id: Option<&str>,
+        traceparent: &str,
+    ) -> Result<Vec<ItemTypeDto>, FetchError> {
+        // queue rather than fail when the limit is reached; the route timeout bounds the wait
+        let _permit = match self.permits.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                tracing::debug!("product lookup waiting for a free slot");
+                self.permits
+                    .acquire()
+                    .await
+                    .map_err(|err| FetchError::Permanent(err.to_string()))?
+            }
+        };
+
+        let mut req = self
+            .http_client
+            .get(format!("{}/{}", self.url, params))
+            .header("traceparent", traceparent);
+        if let Some(request_id) = request_id {
+            req = req.header("x-request-id", request_id);
+        }
+
+        let mut res = req
+            .await
+            .map_err(|err| FetchError::Transient(err.to_string()))?;
+
+        let status = res.status();
+        if status.is_server_error() {
+            return Err(FetchError::Transient(format!(
+                "unexpected status {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Err(FetchError::Permanent(format!(
+                "unexpected status {}",
+                status
+            )));
+        }
+
+        res.body_json::<Vec<ItemTypeDto>>()
+            .await
+            .map_err(|err| FetchError::Permanent(err.to_string()))
+    }
+}
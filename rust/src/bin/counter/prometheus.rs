@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use axum::{
+    extract::MatchedPath,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+const REQUEST_DURATION_METRIC: &str = "http_requests_duration_seconds";
+const EXPONENTIAL_SECONDS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub const ORDERS_PLACED_METRIC: &str = "orders_placed_total";
+pub const ORDER_LINE_ITEMS_METRIC: &str = "order_line_items_total";
+pub const PRODUCT_CIRCUIT_STATE_METRIC: &str = "product_circuit_breaker_state";
+
+pub fn setup_metrics_recorder() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(REQUEST_DURATION_METRIC.to_string()),
+            EXPONENTIAL_SECONDS,
+        )
+        .expect("Metrics buckets configuration failed")
+        .install_recorder()
+        .expect("Metrics recorder installation failed")
+}
+
+pub async fn track_metrics<B>(req: Request<B>, next: Next<B>) -> impl IntoResponse {
+    let start = Instant::now();
+    let path = if let Some(matched_path) = req.extensions().get::<MatchedPath>() {
+        matched_path.as_str().to_owned()
+    } else {
+        req.uri().path().to_owned()
+    };
+    let method = req.method().clone();
+
+    let response: Response = next.run(req).await;
+
+    let latency = start.elapsed().as_secs_f64();
+    let labels = [
+        ("method", method.to_string()),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    metrics::increment_counter!("http_requests_total", &labels);
+    metrics::histogram!(REQUEST_DURATION_METRIC, latency, &labels);
+
+    response
+}
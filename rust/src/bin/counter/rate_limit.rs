@@ -0,0 +1,75 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+// Token bucket shared by every route it guards: refills `rate` tokens a second up to `burst`
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    rate: f64,
+    burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            })),
+            rate: f64::from(rate.max(1)),
+            burst,
+        }
+    }
+
+    // takes a token, or says how long until the next one is available
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+}
+
+pub async fn rate_limit<B>(
+    State(limiter): State<RateLimiter>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Err(wait) = limiter.try_acquire() {
+        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+        tracing::warn!("rate limit exceeded, retry after {}s", retry_after);
+
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            "too many requests",
+        )
+            .into_response();
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,103 @@
+use std::time::Instant;
+
+use sea_orm::{ConnectionTrait, Statement};
+
+use crate::AppState;
+
+// Something `/ready` can probe, named as in READY_CHECKS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    Db,
+    Product,
+    Dapr,
+}
+
+impl Dependency {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dependency::Db => "db",
+            Dependency::Product => "product",
+            Dependency::Dapr => "dapr",
+        }
+    }
+}
+
+// An optional dependency that fails only degrades readiness instead of failing it
+#[derive(Debug, Clone, Copy)]
+pub struct ReadyCheck {
+    pub dependency: Dependency,
+    pub optional: bool,
+}
+
+pub struct ProbeResult {
+    pub check: ReadyCheck,
+    pub error: Option<String>,
+    pub latency_ms: u128,
+}
+
+// `db,dapr,product?`: comma separated names, a trailing `?` marks one optional
+pub fn parse_ready_checks(value: &str) -> Result<Vec<ReadyCheck>, String> {
+    let mut checks: Vec<ReadyCheck> = vec![];
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let (name, optional) = match entry.strip_suffix('?') {
+            Some(name) => (name.trim(), true),
+            None => (entry, false),
+        };
+        let dependency = match name {
+            "db" => Dependency::Db,
+            "product" => Dependency::Product,
+            "dapr" => Dependency::Dapr,
+            other => {
+                return Err(format!(
+                    "unknown dependency `{}`, expected db, product or dapr",
+                    other
+                ))
+            }
+        };
+        if checks.iter().any(|check| check.dependency == dependency) {
+            return Err(format!("`{}` is listed more than once", name));
+        }
+        checks.push(ReadyCheck {
+            dependency,
+            optional,
+        });
+    }
+
+    Ok(checks)
+}
+
+pub async fn probe(app: &AppState, check: ReadyCheck) -> ProbeResult {
+    let started = Instant::now();
+    let result = match check.dependency {
+        Dependency::Db => app
+            .db_conn
+            .execute(Statement::from_string(
+                app.db_conn.get_database_backend(),
+                "SELECT 1".to_string(),
+            ))
+            .await
+            .map(|_| ())
+            .map_err(|err| err.to_string()),
+        Dependency::Product => app.product_client.probe().await,
+        Dependency::Dapr => app
+            .dapr_client
+            .healthz()
+            .await
+            .map_err(|err| err.to_string()),
+    };
+    let latency_ms = started.elapsed().as_millis();
+
+    if let Err(err) = &result {
+        tracing::error!("ready check {} failed: {}", check.dependency.name(), err);
+    }
+
+    ProbeResult {
+        check,
+        error: result.err(),
+        latency_ms,
+    }
+}
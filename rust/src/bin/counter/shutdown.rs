@@ -0,0 +1,90 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use axum::{extract::State, http::Request, middleware::Next, response::Response};
+use tokio::signal;
+
+// Number of requests currently being served, so shutdown can report what it is draining
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+pub async fn track_in_flight<B>(
+    State(in_flight): State<InFlight>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    in_flight.0.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlightGuard(&in_flight);
+
+    next.run(req).await
+}
+
+// Counts the request out even when its future is dropped, e.g. when the client disconnects
+struct InFlightGuard<'a>(&'a InFlight);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub async fn shutdown_signal(in_flight: InFlight) {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("shutting down, draining {} connections", in_flight.count());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_out_requests_dropped_mid_flight() {
+        let in_flight = InFlight::default();
+        let router = Router::new()
+            .route("/", get(std::future::pending::<()>))
+            .layer(middleware::from_fn_with_state(
+                in_flight.clone(),
+                track_in_flight,
+            ));
+
+        let req = Request::get("/").body(Body::empty()).unwrap();
+        let served = tokio::time::timeout(Duration::from_millis(50), router.oneshot(req)).await;
+
+        assert!(served.is_err());
+        assert_eq!(in_flight.count(), 0);
+    }
+}
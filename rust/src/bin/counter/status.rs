@@ -0,0 +1,63 @@
+// Lifecycle shared by orders and their line items, stored as the integer in order_status/item_status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Placed,
+    InProgress,
+    Fulfilled,
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            OrderStatus::Placed => "PLACED",
+            OrderStatus::InProgress => "IN_PROGRESS",
+            OrderStatus::Fulfilled => "FULFILLED",
+            OrderStatus::Cancelled => "CANCELLED",
+        }
+    }
+
+    // forward one step at a time; cancelling goes through its own endpoint
+    pub fn can_transition_to(self, to: OrderStatus) -> bool {
+        matches!(
+            (self, to),
+            (OrderStatus::Placed, OrderStatus::InProgress)
+                | (OrderStatus::InProgress, OrderStatus::Fulfilled)
+        )
+    }
+
+    pub fn is_final(self) -> bool {
+        matches!(self, OrderStatus::Fulfilled | OrderStatus::Cancelled)
+    }
+}
+
+impl From<OrderStatus> for i32 {
+    fn from(status: OrderStatus) -> Self {
+        match status {
+            OrderStatus::Placed => 0,
+            OrderStatus::InProgress => 1,
+            OrderStatus::Fulfilled => 2,
+            OrderStatus::Cancelled => 3,
+        }
+    }
+}
+
+impl TryFrom<i32> for OrderStatus {
+    type Error = i32;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OrderStatus::Placed),
+            1 => Ok(OrderStatus::InProgress),
+            2 => Ok(OrderStatus::Fulfilled),
+            3 => Ok(OrderStatus::Cancelled),
+            other => Err(other),
+        }
+    }
+}
+
+pub fn status_name(value: i32) -> String {
+    OrderStatus::try_from(value)
+        .map_or("UNKNOWN", OrderStatus::name)
+        .to_string()
+}
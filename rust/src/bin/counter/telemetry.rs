@@ -0,0 +1,93 @@
+use axum::http::HeaderMap;
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    sdk::{
+        propagation::TraceContextPropagator,
+        trace::{self, IdGenerator, RandomIdGenerator},
+        Resource,
+    },
+    trace::{SpanContext, TraceContextExt, TraceError, TraceFlags, TraceState, TracerProvider},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const SERVICE_NAME: &str = "counter_api";
+
+// Exports spans over OTLP/gRPC to the collector at `endpoint`
+pub fn init_tracer(endpoint: &str) -> Result<trace::Tracer, TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace_config())
+        .install_batch(opentelemetry::runtime::Tokio)
+}
+
+// Records spans without exporting them, so request spans still carry trace ids when no collector is configured
+pub fn init_local_tracer() -> trace::Tracer {
+    let provider = trace::TracerProvider::builder()
+        .with_config(trace_config())
+        .build();
+    let tracer = provider.tracer(SERVICE_NAME);
+    // the tracer only holds the provider weakly, the global keeps it alive like install_batch does
+    opentelemetry::global::set_tracer_provider(provider);
+    tracer
+}
+
+fn trace_config() -> trace::Config {
+    trace::config().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        SERVICE_NAME,
+    )]))
+}
+
+// The trace an inbound request's W3C traceparent names, or an empty context to start a new one
+pub fn remote_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+// W3C traceparent for an outbound call, continuing the current span or, without one, starting a new trace
+pub fn traceparent() -> String {
+    let context = Span::current().context();
+    let span_context = context.span().span_context().clone();
+    let span_context = if span_context.is_valid() {
+        span_context
+    } else {
+        let ids = RandomIdGenerator::default();
+        SpanContext::new(
+            ids.new_trace_id(),
+            ids.new_span_id(),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        )
+    };
+
+    format!(
+        "00-{:032x}-{:016x}-{:02x}",
+        span_context.trace_id(),
+        span_context.span_id(),
+        span_context.trace_flags()
+    )
+}
+
+pub fn shutdown_tracer() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
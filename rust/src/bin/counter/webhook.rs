@@ -0,0 +1,165 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use dapr_client::backoff_delay;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::status::status_name;
+
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+// Body POSTed to every webhook whenever an order changes status
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderStatusChanged {
+    pub order_id: Uuid,
+    pub order_status: i32,
+    pub status_name: String,
+    pub changed_at: DateTime<Utc>,
+}
+
+impl OrderStatusChanged {
+    pub fn new(order_id: Uuid, order_status: i32) -> Self {
+        OrderStatusChanged {
+            order_id,
+            order_status,
+            status_name: status_name(order_status),
+            changed_at: Utc::now(),
+        }
+    }
+}
+
+// Delivers order status changes to plain HTTP webhooks for integrators without Dapr.
+// Each body is signed with HMAC-SHA256 over the shared secret, sent as `X-Signature: sha256=<hex>`.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    http_client: surf::Client,
+    urls: Arc<Vec<String>>,
+    secret: Arc<Vec<u8>>,
+    timeout: Duration,
+    max_attempts: u32,
+    retry_delay: Duration,
+}
+
+enum DeliveryError {
+    Transient(String),
+    Permanent(String),
+}
+
+impl WebhookNotifier {
+    pub fn new(
+        http_client: surf::Client,
+        urls: Vec<String>,
+        secret: &str,
+        timeout: Duration,
+        max_attempts: u32,
+        retry_delay: Duration,
+    ) -> Self {
+        WebhookNotifier {
+            http_client,
+            urls: Arc::new(urls),
+            secret: Arc::new(secret.as_bytes().to_vec()),
+            timeout,
+            max_attempts: max_attempts.max(1),
+            retry_delay,
+        }
+    }
+
+    // a notifier with no webhooks, used when none are configured
+    pub fn disabled(http_client: surf::Client) -> Self {
+        WebhookNotifier::new(http_client, vec![], "", Duration::ZERO, 1, Duration::ZERO)
+    }
+
+    // deliveries run in the background, so a slow integrator never holds up the request
+    pub fn notify(&self, event: OrderStatusChanged) {
+        if self.urls.is_empty() {
+            return;
+        }
+
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => Arc::new(body),
+            Err(err) => {
+                tracing::error!("encoding webhook for order {}: {}", event.order_id, err);
+                return;
+            }
+        };
+        let signature = Arc::new(self.sign(&body));
+
+        for url in self.urls.iter() {
+            let notifier = self.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let signature = signature.clone();
+            tokio::spawn(async move { notifier.deliver(&url, &body, &signature).await });
+        }
+    }
+
+    fn sign(&self, body: &[u8]) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    async fn deliver(&self, url: &str, body: &[u8], signature: &str) {
+        let mut attempt = 1;
+        loop {
+            let err = match self.send(url, body, signature).await {
+                Ok(()) => return,
+                Err(DeliveryError::Permanent(err)) => {
+                    tracing::error!("webhook {} rejected the delivery: {}", url, err);
+                    return;
+                }
+                Err(DeliveryError::Transient(err)) => err,
+            };
+            if attempt >= self.max_attempts {
+                tracing::error!("webhook {} failed after {} attempts: {}", url, attempt, err);
+                return;
+            }
+
+            let delay = backoff_delay(self.retry_delay, attempt);
+            tracing::warn!(
+                "webhook {} attempt {} failed: {}, retrying in {:?}",
+                url,
+                attempt,
+                err,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    async fn send(&self, url: &str, body: &[u8], signature: &str) -> Result<(), DeliveryError> {
+        let req = self
+            .http_client
+            .post(url)
+            .header(SIGNATURE_HEADER, signature)
+            .content_type(surf::http::mime::JSON)
+            .body_bytes(body);
+
+        let res = tokio::time::timeout(self.timeout, req)
+            .await
+            .map_err(|_| DeliveryError::Transient(format!("timed out after {:?}", self.timeout)))?
+            .map_err(|err| DeliveryError::Transient(err.to_string()))?;
+
+        let status = res.status();
+        if status.is_server_error() || status == surf::StatusCode::TooManyRequests {
+            return Err(DeliveryError::Transient(format!(
+                "unexpected status {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Err(DeliveryError::Permanent(format!(
+                "unexpected status {}",
+                status
+            )));
+        }
+
+        Ok(())
+    }
+}
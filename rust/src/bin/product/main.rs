@@ -1,20 +1,26 @@
-use std::{time::Duration, env};
+use std::{sync::Arc, time::Duration};
 
 use axum::{
     error_handling::HandleErrorLayer,
-    extract::{State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, put},
     Json, Router,
 };
 use clap::Parser;
-use serde::{Serialize, Deserialize};
+use dapr_client::{sidecar_url, DaprClient, DaprError, StateItem, SurfTransport};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tower::{BoxError, ServiceBuilder};
 use tower_http::trace::{self, TraceLayer};
 use tracing::Level;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
+// the counter's menu range, so the catalog never prices an item type that can't be ordered
+#[path = "../counter/item_type.rs"]
+mod item_type;
+
 // App config
 #[derive(Debug, Parser)]
 struct Config {
@@ -22,10 +28,30 @@ struct Config {
     host: String,
     #[clap(default_value = "5001", env)]
     app_port: u16,
+    // used when DAPR_HTTP_PORT isn't set
+    #[clap(long, default_value = "http://localhost:3500", env)]
+    dapr_url: String,
+    // set by Dapr for the app it runs, and then preferred over DAPR_URL
+    #[clap(long, env)]
+    dapr_http_port: Option<u16>,
+    // this service's Dapr app id, set by Dapr alongside DAPR_HTTP_PORT
+    #[clap(long, env)]
+    app_id: Option<String>,
+    // Dapr state store the item types are kept in once changed through PUT
+    #[clap(long, default_value = "statestore", env)]
+    item_type_store: String,
+    // seconds
+    #[clap(long, default_value = "5", env)]
+    http_timeout: u64,
 }
 
+// the whole list is stored under one key, so a save replaces it in one go
+const ITEM_TYPES_KEY: &str = "product-item-types";
+// read-modify-write rounds an upsert gets before giving up on concurrent writers
+const UPSERT_ATTEMPTS: u32 = 5;
+
 // Command, Query and Models
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct ItemType {
     name: String,
@@ -34,20 +60,32 @@ struct ItemType {
     image: String,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpsertItemType {
+    name: String,
+    price: f32,
+    // defaults to the current image, or img/<NAME>.png for a new item type
+    image: Option<String>,
+}
+
+// Every replica reads the item types from the state store, so an upsert on one is seen by all
 #[derive(Clone)]
 struct AppState {
-    item_types: Vec<ItemType>,
+    // served until an item type has been changed through PUT
+    default_item_types: Arc<Vec<ItemType>>,
+    dapr_client: DaprClient<SurfTransport>,
+    store_name: String,
 }
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ItemByTypeQuery {
-    types: String
+    types: String,
 }
 
 #[tokio::main]
 async fn main() {
-    env::set_var("RUST_LOG", "debug");
     dotenv::dotenv().ok();
 
     tracing_subscriber::registry()
@@ -58,77 +96,99 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let mut config = Config::parse();
+    config.dapr_url = sidecar_url(config.dapr_http_port, &config.dapr_url);
+
+    let default_item_types = vec![
+        ItemType {
+            name: "CAPPUCCINO".to_string(),
+            item_type: 0,
+            price: 4.5,
+            image: "img/CAPPUCCINO.png".to_string(),
+        },
+        ItemType {
+            name: "COFFEE_BLACK".to_string(),
+            item_type: 1,
+            price: 3.0,
+            image: "img/COFFEE_BLACK.png".to_string(),
+        },
+        ItemType {
+            name: "COFFEE_WITH_ROOM".to_string(),
+            item_type: 2,
+            price: 3.0,
+            image: "img/COFFEE_WITH_ROOM.png".to_string(),
+        },
+        ItemType {
+            name: "ESPRESSO".to_string(),
+            item_type: 3,
+            price: 3.5,
+            image: "img/ESPRESSO.png".to_string(),
+        },
+        ItemType {
+            name: "ESPRESSO_DOUBLE".to_string(),
+            item_type: 4,
+            price: 4.5,
+            image: "img/ESPRESSO_DOUBLE.png".to_string(),
+        },
+        ItemType {
+            name: "LATTE".to_string(),
+            item_type: 5,
+            price: 4.5,
+            image: "img/LATTE.png".to_string(),
+        },
+        ItemType {
+            name: "CAKEPOP".to_string(),
+            item_type: 6,
+            price: 2.5,
+            image: "img/CAKEPOP.png".to_string(),
+        },
+        ItemType {
+            name: "CROISSANT".to_string(),
+            item_type: 7,
+            price: 3.25,
+            image: "img/CROISSANT.png".to_string(),
+        },
+        ItemType {
+            name: "MUFFIN".to_string(),
+            item_type: 8,
+            price: 3.0,
+            image: "img/MUFFIN.png".to_string(),
+        },
+        ItemType {
+            name: "CROISSANT_CHOCOLATE".to_string(),
+            item_type: 9,
+            price: 3.5,
+            image: "img/CROISSANT_CHOCOLATE.png".to_string(),
+        },
+    ];
+
+    let http_client: surf::Client = surf::Config::new()
+        .set_timeout(Some(Duration::from_secs(config.http_timeout)))
+        .try_into()
+        .expect("HTTP client creation failed");
+    let dapr_client = DaprClient::new(SurfTransport(http_client), &config.dapr_url);
+    tracing::info!(
+        "running as Dapr app {} with the sidecar at {}",
+        config.app_id.as_deref().unwrap_or("(unset)"),
+        config.dapr_url
+    );
+
     let state = AppState {
-        item_types: vec![
-            ItemType {
-                name: "CAPPUCCINO".to_string(),
-                item_type: 0,
-                price: 4.5,
-                image: "img/CAPPUCCINO.png".to_string(),
-            },
-            ItemType {
-                name: "COFFEE_BLACK".to_string(),
-                item_type: 1,
-                price: 3.0,
-                image: "img/COFFEE_BLACK.png".to_string(),
-            },
-            ItemType {
-                name: "COFFEE_WITH_ROOM".to_string(),
-                item_type: 2,
-                price: 3.0,
-                image: "img/COFFEE_WITH_ROOM.png".to_string(),
-            },
-            ItemType {
-                name: "ESPRESSO".to_string(),
-                item_type: 3,
-                price: 3.5,
-                image: "img/ESPRESSO.png".to_string(),
-            },
-            ItemType {
-                name: "ESPRESSO_DOUBLE".to_string(),
-                item_type: 4,
-                price: 4.5,
-                image: "img/ESPRESSO_DOUBLE.png".to_string(),
-            },
-            ItemType {
-                name: "LATTE".to_string(),
-                item_type: 5,
-                price: 4.5,
-                image: "img/LATTE.png".to_string(),
-            },
-            ItemType {
-                name: "CAKEPOP".to_string(),
-                item_type: 6,
-                price: 2.5,
-                image: "img/CAKEPOP.png".to_string(),
-            },
-            ItemType {
-                name: "CROISSANT".to_string(),
-                item_type: 7,
-                price: 3.25,
-                image: "img/CROISSANT.png".to_string(),
-            },
-            ItemType {
-                name: "MUFFIN".to_string(),
-                item_type: 8,
-                price: 3.0,
-                image: "img/MUFFIN.png".to_string(),
-            },
-            ItemType {
-                name: "CROISSANT_CHOCOLATE".to_string(),
-                item_type: 9,
-                price: 3.5,
-                image: "img/CROISSANT_CHOCOLATE.png".to_string(),
-            },
-        ],
+        default_item_types: Arc::new(default_item_types),
+        dapr_client,
+        store_name: config.item_type_store.clone(),
     };
 
     let app = Router::new()
         .route("/", get(home_handler))
         .route("/v1/api/item-types", get(item_types_handler))
         .route("/v1-get-item-types", get(item_types_handler))
-        .route("/v1/api/items-by-types/:types", get(item_by_types_handler))
+        .route(
+            "/v1/api/items-by-types/:types",
+            get(item_by_types_path_handler),
+        )
         .route("/v1-get-items-by-types", get(item_by_types_handler))
+        .route("/v1/api/items/:type", put(upsert_item_type_handler))
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(|error: BoxError| async move {
@@ -151,7 +211,6 @@ async fn main() {
         )
         .with_state(state);
 
-    let config = Config::parse();
     let addr: String = format!("{}:{}", config.host.as_str(), config.app_port);
 
     tracing::debug!("listening on {}", addr);
@@ -166,24 +225,195 @@ async fn item_by_types_handler(
     State(app): State<AppState>,
     Json(query): Json<ItemByTypeQuery>,
 ) -> impl IntoResponse {
+    let item_types = current_item_types(&app).await;
+    (
+        StatusCode::OK,
+        Json(find_items_by_types(&item_types, &query.types)),
+    )
+}
+
+async fn item_by_types_path_handler(
+    State(app): State<AppState>,
+    Path(types): Path<String>,
+) -> impl IntoResponse {
+    let item_types = current_item_types(&app).await;
+    (
+        StatusCode::OK,
+        Json(find_items_by_types(&item_types, &types)),
+    )
+}
+
+// the stored list, or the built-in one while nothing has been stored or the store can't be reached
+async fn current_item_types(app: &AppState) -> Vec<ItemType> {
+    match load_item_types(app).await {
+        Ok((item_types, _)) => item_types,
+        Err(err) => {
+            tracing::warn!(
+                "loading stored item types failed, using the built-in list: {}",
+                err
+            );
+            app.default_item_types.to_vec()
+        }
+    }
+}
+
+// the list along with the ETag to save it back at, None while only the built-in list exists
+async fn load_item_types(app: &AppState) -> Result<(Vec<ItemType>, Option<String>), DaprError> {
+    let entry = app
+        .dapr_client
+        .get_state_entry::<Vec<ItemType>>(&app.store_name, ITEM_TYPES_KEY)
+        .await?;
+    let item_types = entry
+        .value
+        .unwrap_or_else(|| app.default_item_types.to_vec());
+
+    Ok((item_types, entry.etag))
+}
+
+// unknown or malformed ids are skipped, so the result may be shorter than the request
+fn find_items_by_types(item_types: &[ItemType], types: &str) -> Vec<ItemType> {
     let mut temp: Vec<ItemType> = Vec::new();
 
-    for i in app.item_types {
-        let parts = query.types.split(',');
+    for j in types.split(',') {
+        let Ok(item_type) = j.trim().parse::<i8>() else {
+            continue;
+        };
 
-        let ii = i.clone();
-        for j in parts {
-            if ii.item_type.to_string().as_str() == j {
-                temp.push(ii.clone())
-            }
+        if let Some(ii) = item_types.iter().find(|i| i.item_type == item_type) {
+            temp.push(ii.clone())
         }
     }
 
-    (StatusCode::OK, Json(temp))
+    temp
+}
+
+async fn item_types_handler(State(app): State<AppState>, headers: HeaderMap) -> Response {
+    let body = serde_json::to_string(&current_item_types(&app).await).unwrap();
+    let etag = etag_for(&body);
+    if if_none_match(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::ETAG, etag),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+// strong validator over the serialized body; SHA-256 stays the same across builds and replicas, unlike std's hasher
+fn etag_for(body: &str) -> String {
+    format!("\"{:x}\"", Sha256::digest(body))
+}
+
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|candidates| {
+            candidates.split(',').any(|candidate| {
+                let candidate = candidate.trim();
+                candidate == "*" || candidate.trim_start_matches("W/") == etag
+            })
+        })
+        .unwrap_or(false)
 }
 
-async fn item_types_handler(State(app): State<AppState>) -> impl IntoResponse {
-    Json(app.item_types)
+// creates the item type or replaces it, answering 201 or 200 with the stored record
+async fn upsert_item_type_handler(
+    State(app): State<AppState>,
+    Path(item_type): Path<i32>,
+    Json(input): Json<UpsertItemType>,
+) -> Response {
+    let item_type = match item_type::ItemType::try_from(item_type) {
+        // on the menu, so it fits the stored i8
+        Ok(item_type) => i32::from(item_type) as i8,
+        Err(err) => return (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response(),
+    };
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, "name must not be empty").into_response();
+    }
+    if !input.price.is_finite() || input.price <= 0.0 {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "price must be greater than 0",
+        )
+            .into_response();
+    }
+
+    // first-write on the ETag read, so concurrent upserts from any replica apply one after the other
+    let mut attempt = 1;
+    let (existed, record) = loop {
+        match upsert_item_type(&app, item_type, &name, &input).await {
+            Err(err) if err.is_etag_mismatch() && attempt < UPSERT_ATTEMPTS => attempt += 1,
+            Err(err) if err.is_etag_mismatch() => {
+                return (
+                    StatusCode::CONFLICT,
+                    format!(
+                        "item type {} is being changed concurrently, retry",
+                        item_type
+                    ),
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                tracing::error!("storing item type {}: {}", item_type, err);
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    format!("could not store item type {}", item_type),
+                )
+                    .into_response();
+            }
+            Ok(upserted) => break upserted,
+        }
+    };
+
+    let status = if existed {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    (status, Json(record)).into_response()
+}
+
+// one read-modify-write round, answering whether the item type existed and the record stored
+async fn upsert_item_type(
+    app: &AppState,
+    item_type: i8,
+    name: &str,
+    input: &UpsertItemType,
+) -> Result<(bool, ItemType), DaprError> {
+    let (mut item_types, etag) = load_item_types(app).await?;
+    let existing = item_types.iter().position(|i| i.item_type == item_type);
+    let image = input
+        .image
+        .clone()
+        .or_else(|| existing.map(|index| item_types[index].image.clone()))
+        .unwrap_or_else(|| format!("img/{}.png", name));
+    let record = ItemType {
+        name: name.to_string(),
+        item_type,
+        price: input.price,
+        image,
+    };
+    match existing {
+        Some(index) => item_types[index] = record.clone(),
+        None => {
+            item_types.push(record.clone());
+            item_types.sort_by_key(|i| i.item_type);
+        }
+    }
+
+    let entry = StateItem::new(ITEM_TYPES_KEY, &item_types)?.first_write(etag);
+    app.dapr_client
+        .save_state(&app.store_name, &[entry])
+        .await?;
+
+    Ok((existing.is_some(), record))
 }
 
 async fn home_handler() -> impl IntoResponse {